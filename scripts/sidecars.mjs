@@ -0,0 +1,33 @@
+// Builds nera-helper and the nera CLI and stages them where the bundler's
+// `externalBin` expects them: src-tauri/binaries/<name>-<target triple>.
+// They aren't in tauri.conf.json itself because tauri-build refuses to build
+// when the staged files are missing, which would break plain `cargo build`.
+
+import { execFileSync } from "node:child_process";
+import { copyFileSync, mkdirSync } from "node:fs";
+import { join } from "node:path";
+
+const SIDECARS = ["nera-helper", "nera"];
+
+const triple = execFileSync("rustc", ["-vV"], { encoding: "utf8" }).match(/^host: (\S+)$/m)[1];
+const ext = process.platform === "win32" ? ".exe" : "";
+
+execFileSync(
+  "cargo",
+  [
+    "build",
+    "--release",
+    "--manifest-path",
+    "src-tauri/Cargo.toml",
+    ...SIDECARS.flatMap((name) => ["--bin", name]),
+  ],
+  { stdio: "inherit" },
+);
+
+mkdirSync("src-tauri/binaries", { recursive: true });
+for (const name of SIDECARS) {
+  copyFileSync(
+    join("src-tauri/target/release", name + ext),
+    join("src-tauri/binaries", `${name}-${triple}${ext}`),
+  );
+}
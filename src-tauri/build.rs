@@ -8,5 +8,16 @@
   The source code and binaries are protected by copyright law and international treaties.
 */
 fn main() {
+    // The app refuses API connections without pins, so a release build
+    // without them would be unable to sign in
+    println!("cargo:rerun-if-env-changed=NERA_API_PIN_PRIMARY");
+    println!("cargo:rerun-if-env-changed=NERA_API_PIN_BACKUP");
+    let pinned = ["NERA_API_PIN_PRIMARY", "NERA_API_PIN_BACKUP"]
+        .iter()
+        .any(|name| std::env::var(name).map_or(false, |pin| !pin.trim().is_empty()));
+    if !pinned && std::env::var("PROFILE").as_deref() == Ok("release") {
+        panic!("Release builds need NERA_API_PIN_PRIMARY and/or NERA_API_PIN_BACKUP set to the API's SPKI pins.");
+    }
+
     tauri_build::build()
 }
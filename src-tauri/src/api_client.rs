@@ -0,0 +1,1018 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Client for the Nera account API.
+//
+// One shared blocking client with timeouts; the base URL comes from settings.
+// Everything goes over HTTPS with the server key pinned (see `pinning`).
+// Responses are decoded into typed structs and failures mapped to `ApiError`.
+// Requests that never reached the server are always retried; timeouts and 5xx
+// are only retried for idempotent methods, so a POST is never sent twice.
+//
+// Login hands out an access/refresh token pair. Authenticated calls carry the
+// access token, refresh it shortly before it expires (or on a 401), and emit
+// `session-expired` if the server won't refresh it any more.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+
+use reqwest::{blocking::Client, Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::{
+    append_log, error::NeraError, events, load_settings, pinning, secrets, update_settings,
+    AppSettings, VpnState,
+};
+
+pub const DEFAULT_BASE_URL: &str = match option_env!("NERA_API_URL") {
+    Some(url) => url,
+    None => "https://45.76.106.63:3000",
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_ATTEMPTS: u32 = 3;
+const BACKOFF_BASE: Duration = Duration::from_millis(300);
+// Server messages longer than this are cut before being shown
+const MAX_MESSAGE_LEN: usize = 200;
+// Refresh this long before the access token actually expires
+const REFRESH_MARGIN_SECS: i64 = 30;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// The server couldn't be reached (DNS, refused, TLS, ...).
+    Network(String),
+    Timeout,
+    /// 4xx, or a 2xx carrying an `error` field. `message` is the server's.
+    Client {
+        status: u16,
+        message: String,
+    },
+    /// 5xx.
+    Server {
+        status: u16,
+        message: String,
+    },
+    /// The response wasn't what we expected.
+    Decode(String),
+    /// No session, or the server refused to refresh it. Sign in again.
+    SessionExpired,
+    /// The server's certificate key isn't one of the pinned ones.
+    PinMismatch,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(e) => write!(f, "Could not reach the Nera server: {e}"),
+            ApiError::Timeout => write!(f, "The Nera server did not respond in time."),
+            ApiError::Client { status, message } if message.is_empty() => {
+                write!(f, "Request rejected by the Nera server ({status}).")
+            }
+            ApiError::Client { message, .. } => write!(f, "{message}"),
+            ApiError::Server { status, message } => {
+                write!(f, "Nera server error ({status}): {message}")
+            }
+            ApiError::Decode(e) => write!(f, "Unexpected response from the Nera server: {e}"),
+            ApiError::SessionExpired => {
+                write!(f, "Your session has expired. Please sign in again.")
+            }
+            ApiError::PinMismatch => write!(
+                f,
+                "The Nera server's certificate did not match the expected key. \
+                 Your connection may be intercepted."
+            ),
+        }
+    }
+}
+
+impl ApiError {
+    fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            ApiError::Network(_) => true,
+            ApiError::Timeout | ApiError::Server { .. } => idempotent,
+            ApiError::Client { .. }
+            | ApiError::Decode(_)
+            | ApiError::SessionExpired
+            | ApiError::PinMismatch => false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuthRequest {
+    pub email: String,
+    pub password: String,
+    pub public_key: String,
+    /// Shown in the account's device list.
+    pub device_name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthResponse {
+    #[serde(default)]
+    pub success: bool,
+    /// Tunnel address assigned to this device's key.
+    pub ip: String,
+    // Tokens stay in the backend; the frontend only gets `ip`
+    #[serde(default, skip_serializing)]
+    pub access_token: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub refresh_token: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub expires_in: Option<i64>,
+    /// WireGuard preshared keys for this device's key, by server key.
+    #[serde(default, skip_serializing)]
+    pub preshared_keys: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RefreshResponse {
+    access_token: String,
+    // Servers that rotate refresh tokens send a new one
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct RefreshRequest<'a> {
+    refresh_token: &'a str,
+}
+
+/// Token pair from the last login. In settings (and stashed identities) both
+/// tokens are sealed with `secrets`; in memory they're plain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Unix seconds; `None` if the server didn't say.
+    pub expires_at: Option<i64>,
+}
+
+impl AuthSession {
+    fn map_tokens(&self, f: fn(&str) -> Result<String, String>) -> Result<Self, String> {
+        Ok(AuthSession {
+            access_token: f(&self.access_token)?,
+            refresh_token: f(&self.refresh_token)?,
+            expires_at: self.expires_at,
+        })
+    }
+
+    /// Copy with both tokens sealed, for storing.
+    pub fn seal(&self) -> Result<Self, String> {
+        self.map_tokens(secrets::seal)
+    }
+
+    /// Copy of a stored session with both tokens opened.
+    pub fn open(&self) -> Result<Self, String> {
+        self.map_tokens(secrets::open)
+    }
+
+    fn needs_refresh(&self) -> bool {
+        self.expires_at
+            .map(|at| chrono::Utc::now().timestamp() + REFRESH_MARGIN_SECS >= at)
+            .unwrap_or(false)
+    }
+}
+
+/// The session stored in `settings`, opened. One that can't be opened (e.g.
+/// from before tokens were sealed) means signing in again.
+fn stored_session(settings: &AppSettings) -> Option<AuthSession> {
+    match settings.auth_session.as_ref()?.open() {
+        Ok(session) => Some(session),
+        Err(e) => {
+            append_log(&format!(
+                "Stored API session unreadable ({e}); sign-in required."
+            ))
+            .ok();
+            None
+        }
+    }
+}
+
+fn expires_at(expires_in: Option<i64>) -> Option<i64> {
+    expires_in.map(|secs| chrono::Utc::now().timestamp() + secs)
+}
+
+#[derive(Serialize)]
+pub struct RotateKeyRequest {
+    pub old_public_key: String,
+    pub new_public_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct RotateKeyResponse {
+    /// Tunnel address for the new key (normally unchanged).
+    pub ip: String,
+    /// Preshared keys issued for the new key, by server key.
+    #[serde(default)]
+    pub preshared_keys: BTreeMap<String, String>,
+}
+
+/// Registers the separate key a multi-hop inner tunnel uses; the response
+/// has the same shape as a rotation's.
+#[derive(Serialize)]
+pub struct RegisterHopKeyRequest {
+    pub device_public_key: String,
+    pub hop_public_key: String,
+}
+
+#[derive(Serialize)]
+struct RevokeKeyRequest<'a> {
+    public_key: &'a str,
+}
+
+#[derive(Serialize)]
+struct RotatePresharedKeysRequest<'a> {
+    public_key: &'a str,
+}
+
+#[derive(Deserialize)]
+struct PresharedKeysResponse {
+    preshared_keys: BTreeMap<String, String>,
+}
+
+// Shapes the server uses for error bodies
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(alias = "message")]
+    error: Option<String>,
+}
+
+fn server_message(body: &str) -> String {
+    let message = serde_json::from_str::<ErrorBody>(body)
+        .ok()
+        .and_then(|b| b.error)
+        .unwrap_or_else(|| body.trim().to_string());
+    if message.len() > MAX_MESSAGE_LEN {
+        let mut end = MAX_MESSAGE_LEN;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &message[..end])
+    } else {
+        message
+    }
+}
+
+fn transport_error(e: reqwest::Error) -> ApiError {
+    if pinning::is_pin_error(&e) {
+        ApiError::PinMismatch
+    } else if e.is_timeout() {
+        ApiError::Timeout
+    } else {
+        ApiError::Network(e.to_string())
+    }
+}
+
+fn normalize_base_url(url: &str) -> Result<String, String> {
+    let parsed =
+        reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid API URL {url}: {e}"))?;
+    if parsed.scheme() != "https" || parsed.host_str().is_none() {
+        return Err(format!("API URL must be https with a host: {url}"));
+    }
+    Ok(parsed.as_str().trim_end_matches('/').to_string())
+}
+
+// No fallback client: one without the pinned TLS config would silently
+// accept any certificate the OS trusts
+fn pinned_http(tls: rustls::ClientConfig) -> Result<Client, NeraError> {
+    Client::builder()
+        .use_preconfigured_tls(tls)
+        .https_only(true)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| NeraError::Internal(format!("Failed to build the API client: {e}")))
+}
+
+pub struct NeraApiClient {
+    http: Client,
+    base_url: RwLock<String>,
+    session: Mutex<Option<AuthSession>>,
+    // Held while refreshing so concurrent calls don't all refresh at once
+    refresh_lock: Mutex<()>,
+    // For `session-expired`; set once the app is up
+    app: Mutex<Option<AppHandle>>,
+    // Whether session changes are written to settings
+    persist: bool,
+}
+
+impl NeraApiClient {
+    /// Must be called outside the async runtime (the blocking client owns one).
+    pub fn from_settings() -> Result<Self, NeraError> {
+        let settings = load_settings();
+        let base_url = settings
+            .api_base_url
+            .as_deref()
+            .and_then(|url| normalize_base_url(url).ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+
+        let http = pinned_http(pinning::api_tls_config())?;
+        let session = stored_session(&settings);
+        Ok(Self::with_client(http, base_url, session, true))
+    }
+
+    fn with_client(
+        http: Client,
+        base_url: String,
+        session: Option<AuthSession>,
+        persist: bool,
+    ) -> Self {
+        NeraApiClient {
+            http,
+            base_url: RwLock::new(base_url),
+            session: Mutex::new(session),
+            refresh_lock: Mutex::new(()),
+            app: Mutex::new(None),
+            persist,
+        }
+    }
+
+    pub fn attach(&self, app: AppHandle) {
+        *self.app.lock().unwrap() = Some(app);
+    }
+
+    pub fn has_session(&self) -> bool {
+        self.session.lock().unwrap().is_some()
+    }
+
+    fn set_session(&self, session: Option<AuthSession>) {
+        *self.session.lock().unwrap() = session.clone();
+        if !self.persist {
+            return;
+        }
+        // Not persisted if it can't be sealed; it still works until restart
+        let sealed = match session.as_ref().map(AuthSession::seal).transpose() {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                append_log(&format!("Could not store the API session: {e}")).ok();
+                None
+            }
+        };
+        update_settings(|settings| settings.auth_session = sealed);
+    }
+
+    fn expire_session(&self) {
+        self.set_session(None);
+        append_log("API session expired; sign-in required.").ok();
+        if let Some(app) = self.app.lock().unwrap().as_ref() {
+            events::emit(app, "session-expired", ()).ok();
+        }
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url.read().unwrap().clone()
+    }
+
+    fn set_base_url(&self, url: String) {
+        *self.base_url.write().unwrap() = url;
+    }
+
+    fn send_once<T: DeserializeOwned>(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+        bearer: Option<&str>,
+    ) -> Result<T, ApiError> {
+        let mut request = self
+            .http
+            .request(method.clone(), format!("{}{path}", self.base_url()));
+        if let Some(token) = bearer {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().map_err(transport_error)?;
+
+        let status = response.status();
+        let text = response.text().map_err(transport_error)?;
+
+        if status.is_server_error() {
+            return Err(ApiError::Server {
+                status: status.as_u16(),
+                message: server_message(&text),
+            });
+        }
+        if !status.is_success() {
+            return Err(ApiError::Client {
+                status: status.as_u16(),
+                message: server_message(&text),
+            });
+        }
+
+        // Some endpoints report failures as 200 with an `error` field
+        if let Ok(ErrorBody { error: Some(e) }) = serde_json::from_str::<ErrorBody>(&text) {
+            return Err(ApiError::Client {
+                status: StatusCode::OK.as_u16(),
+                message: e,
+            });
+        }
+
+        serde_json::from_str(&text).map_err(|e| ApiError::Decode(e.to_string()))
+    }
+
+    fn send_with_retries<T: DeserializeOwned>(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+        bearer: Option<&str>,
+    ) -> Result<T, ApiError> {
+        let idempotent = matches!(
+            *method,
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+        );
+
+        let mut attempt = 1;
+        loop {
+            match self.send_once(method, path, body, bearer) {
+                Err(e) if attempt < MAX_ATTEMPTS && e.is_retryable(idempotent) => {
+                    let delay = BACKOFF_BASE * 2u32.pow(attempt - 1)
+                        + Duration::from_millis(rand::random::<u64>() % 100);
+                    append_log(&format!(
+                        "API {method} {path} failed ({e}); retrying in {} ms",
+                        delay.as_millis()
+                    ))
+                    .ok();
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends `method path` with an optional JSON body, retrying with
+    /// exponential backoff where that's safe.
+    pub fn request<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T, ApiError> {
+        let body = to_json(body)?;
+        self.send_with_retries(&method, path, body.as_ref(), None)
+    }
+
+    /// Like `request`, but as the signed-in user. Refreshes the access token
+    /// when it's about to expire and once more if the server answers 401.
+    pub fn authed_request<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T, ApiError> {
+        let body = to_json(body)?;
+
+        let token = self.access_token(false)?;
+        match self.send_with_retries(&method, path, body.as_ref(), Some(&token)) {
+            Err(ApiError::Client { status: 401, .. }) => {
+                let token = self.access_token(true)?;
+                self.send_with_retries(&method, path, body.as_ref(), Some(&token))
+                    .map_err(|e| match e {
+                        ApiError::Client { status: 401, .. } => {
+                            self.expire_session();
+                            ApiError::SessionExpired
+                        }
+                        other => other,
+                    })
+            }
+            result => result,
+        }
+    }
+
+    /// Current access token, refreshed first if `force` or it's about to expire.
+    fn access_token(&self, force: bool) -> Result<String, ApiError> {
+        let _guard = self.refresh_lock.lock().unwrap();
+        let session = self
+            .session
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ApiError::SessionExpired)?;
+
+        if !force && !session.needs_refresh() {
+            return Ok(session.access_token);
+        }
+
+        let refreshed: Result<RefreshResponse, ApiError> = self.send_with_retries(
+            &Method::POST,
+            "/api/refresh",
+            to_json(Some(&RefreshRequest {
+                refresh_token: &session.refresh_token,
+            }))?
+            .as_ref(),
+            None,
+        );
+
+        match refreshed {
+            Ok(r) => {
+                let access_token = r.access_token.clone();
+                self.set_session(Some(AuthSession {
+                    access_token: r.access_token,
+                    refresh_token: r.refresh_token.unwrap_or(session.refresh_token),
+                    expires_at: expires_at(r.expires_in),
+                }));
+                append_log("API session refreshed.").ok();
+                Ok(access_token)
+            }
+            // The server rejected the refresh token: the session is over
+            Err(ApiError::Client { .. }) => {
+                self.expire_session();
+                Err(ApiError::SessionExpired)
+            }
+            // Couldn't ask; keep the session and let the caller retry later
+            Err(e) => Err(e),
+        }
+    }
+
+    fn start_session(&self, response: &AuthResponse) {
+        match (&response.access_token, &response.refresh_token) {
+            (Some(access), Some(refresh)) => self.set_session(Some(AuthSession {
+                access_token: access.clone(),
+                refresh_token: refresh.clone(),
+                expires_at: expires_at(response.expires_in),
+            })),
+            // Older servers don't issue tokens
+            _ => self.set_session(None),
+        }
+    }
+
+    pub fn register(&self, request: &AuthRequest) -> Result<AuthResponse, ApiError> {
+        let response: AuthResponse = self.request(Method::POST, "/api/register", Some(request))?;
+        self.start_session(&response);
+        Ok(response)
+    }
+
+    pub fn login(&self, request: &AuthRequest) -> Result<AuthResponse, ApiError> {
+        let response: AuthResponse = self.request(Method::POST, "/api/login", Some(request))?;
+        self.start_session(&response);
+        Ok(response)
+    }
+
+    /// Registers a new device key alongside the current one. Both stay valid
+    /// until the old one is revoked.
+    pub fn rotate_key(&self, request: &RotateKeyRequest) -> Result<RotateKeyResponse, ApiError> {
+        self.authed_request(Method::POST, "/api/keys/rotate", Some(request))
+    }
+
+    pub fn register_hop_key(
+        &self,
+        request: &RegisterHopKeyRequest,
+    ) -> Result<RotateKeyResponse, ApiError> {
+        self.authed_request(Method::POST, "/api/keys/hop", Some(request))
+    }
+
+    pub fn revoke_key(&self, public_key: &str) -> Result<(), ApiError> {
+        self.authed_request::<serde_json::Value, _>(
+            Method::POST,
+            "/api/keys/revoke",
+            Some(&RevokeKeyRequest { public_key }),
+        )
+        .map(|_| ())
+    }
+
+    /// Issues fresh preshared keys for `public_key`. The old ones stop working
+    /// as soon as this returns.
+    pub fn rotate_preshared_keys(
+        &self,
+        public_key: &str,
+    ) -> Result<BTreeMap<String, String>, ApiError> {
+        self.authed_request::<PresharedKeysResponse, _>(
+            Method::POST,
+            "/api/keys/psk/rotate",
+            Some(&RotatePresharedKeysRequest { public_key }),
+        )
+        .map(|r| r.preshared_keys)
+    }
+
+    /// Revokes the refresh token server-side (best effort) and forgets the session.
+    pub fn logout(&self) -> Result<(), ApiError> {
+        let session = self.session.lock().unwrap().clone();
+        self.set_session(None);
+
+        match session {
+            Some(s) => self.revoke_session(&s),
+            None => Ok(()),
+        }
+    }
+
+    /// Revokes `session` server-side without touching the active one.
+    pub fn revoke_session(&self, session: &AuthSession) -> Result<(), ApiError> {
+        let body = to_json(Some(&RefreshRequest {
+            refresh_token: &session.refresh_token,
+        }))?;
+        self.send_with_retries::<serde_json::Value>(
+            &Method::POST,
+            "/api/logout",
+            body.as_ref(),
+            Some(&session.access_token),
+        )
+        .map(|_| ())
+    }
+
+    /// Picks up the session from settings after another identity became active.
+    pub fn reload_session(&self) {
+        *self.session.lock().unwrap() = stored_session(&load_settings());
+    }
+}
+
+fn to_json<B: Serialize>(body: Option<&B>) -> Result<Option<serde_json::Value>, ApiError> {
+    match body {
+        Some(b) => serde_json::to_value(b)
+            .map(Some)
+            .map_err(|e| ApiError::Decode(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionStatus {
+    pub signed_in: bool,
+    /// Whether the server confirmed the session just now (false when offline).
+    pub verified: bool,
+}
+
+// --- Tauri Commands ---
+
+/// Checks the stored session against the server, refreshing it if needed.
+/// Emits `session-expired` if it can't be kept alive.
+#[tauri::command]
+pub async fn get_session_status(state: State<'_, VpnState>) -> Result<SessionStatus, NeraError> {
+    let api = state.api.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if !api.has_session() {
+            return SessionStatus {
+                signed_in: false,
+                verified: true,
+            };
+        }
+        match api.authed_request::<serde_json::Value, ()>(Method::GET, "/api/session", None) {
+            Ok(_) => SessionStatus {
+                signed_in: true,
+                verified: true,
+            },
+            Err(ApiError::SessionExpired) => SessionStatus {
+                signed_in: false,
+                verified: true,
+            },
+            Err(e) => {
+                append_log(&format!("Session check failed: {e}")).ok();
+                SessionStatus {
+                    signed_in: true,
+                    verified: false,
+                }
+            }
+        }
+    })
+    .await
+    .map_err(|e| NeraError::Internal(format!("Session check failed: {e}")))
+}
+
+#[tauri::command]
+pub fn get_api_base_url(state: State<'_, VpnState>) -> String {
+    state.api.base_url()
+}
+
+/// Points the app at another API server; `None` restores the default.
+#[tauri::command]
+pub fn set_api_base_url(
+    state: State<'_, VpnState>,
+    url: Option<String>,
+) -> Result<String, NeraError> {
+    let normalized = match url.as_deref() {
+        Some(u) => Some(normalize_base_url(u).map_err(NeraError::InvalidInput)?),
+        None => None,
+    };
+
+    update_settings(|settings| settings.api_base_url = normalized.clone());
+
+    let effective = normalized.unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+    state.api.set_base_url(effective.clone());
+    append_log(&format!("API base URL set to {effective}")).ok();
+    Ok(effective)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// What the mock server saw of one request.
+    pub(crate) struct Seen {
+        pub method: String,
+        pub path: String,
+        pub bearer: Option<String>,
+    }
+
+    pub(crate) enum Reply {
+        Status(u16, &'static str),
+        // Accept the request and never answer
+        Hang,
+    }
+
+    /// Plain-HTTP stand-in for the API answering one connection per reply,
+    /// in order. Returns its base URL and what it was asked.
+    pub(crate) fn mock_server(replies: Vec<Reply>) -> (String, mpsc::Receiver<Seen>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for reply in replies {
+                let (stream, _) = match listener.accept() {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut bearer = None;
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        "authorization" => {
+                            bearer = value.trim().strip_prefix("Bearer ").map(str::to_string)
+                        }
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let _ = tx.send(Seen {
+                    method,
+                    path,
+                    bearer,
+                });
+
+                let mut stream = stream;
+                match reply {
+                    Reply::Status(status, body) => {
+                        let _ = write!(
+                            stream,
+                            "HTTP/1.1 {status} X\r\nContent-Type: application/json\r\n\
+                             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        );
+                    }
+                    Reply::Hang => {
+                        thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            }
+        });
+        (base_url, rx)
+    }
+
+    pub(crate) fn client(
+        base_url: String,
+        session: Option<AuthSession>,
+        timeout: Duration,
+    ) -> NeraApiClient {
+        let http = Client::builder().timeout(timeout).build().unwrap();
+        NeraApiClient::with_client(http, base_url, session, false)
+    }
+
+    /// HTTPS stand-in for the API using the test certificate, answering
+    /// `{"ok":true}` on each connection whose handshake succeeds.
+    fn tls_server(connections: usize) -> String {
+        let cert = include_bytes!("../tests/fixtures/localhost.pem");
+        let key = include_bytes!("../tests/fixtures/localhost.key");
+        let identity = native_tls::Identity::from_pkcs8(cert, key).unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("https://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let tcp = match listener.accept() {
+                    Ok((tcp, _)) => tcp,
+                    Err(_) => return,
+                };
+                // A client refusing the pin aborts the handshake
+                let mut tls = match acceptor.accept(tcp) {
+                    Ok(tls) => tls,
+                    Err(_) => continue,
+                };
+                let mut reader = BufReader::new(&mut tls);
+                let mut line = String::new();
+                while reader.read_line(&mut line).map_or(false, |n| n > 0) {
+                    if line == "\r\n" {
+                        break;
+                    }
+                    line.clear();
+                }
+                let body = r#"{"ok":true}"#;
+                let _ = write!(
+                    tls,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        base_url
+    }
+
+    fn pinned_client(base_url: String, pin: &str) -> NeraApiClient {
+        let pins = vec![pinning::decode_pin(pin).unwrap()];
+        let http = pinned_http(pinning::pinned_tls_config(pins)).unwrap();
+        NeraApiClient::with_client(http, base_url, None, false)
+    }
+
+    pub(crate) fn session(access_token: &str) -> AuthSession {
+        AuthSession {
+            access_token: access_token.to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn idempotent_requests_retry_server_errors() {
+        let (url, seen) = mock_server(vec![
+            Reply::Status(503, r#"{"error":"busy"}"#),
+            Reply::Status(502, ""),
+            Reply::Status(200, r#"{"ok":true}"#),
+        ]);
+        let api = client(url, None, REQUEST_TIMEOUT);
+
+        let value: serde_json::Value = api.request::<_, ()>(Method::GET, "/api/x", None).unwrap();
+        assert_eq!(value["ok"], true);
+        assert_eq!(seen.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn posts_are_not_retried_and_map_5xx_to_server() {
+        let (url, seen) = mock_server(vec![
+            Reply::Status(500, r#"{"message":"db down"}"#),
+            Reply::Status(200, "{}"),
+        ]);
+        let api = client(url, None, REQUEST_TIMEOUT);
+
+        let result = api.request::<serde_json::Value, _>(Method::POST, "/api/x", Some(&()));
+        match result {
+            Err(ApiError::Server { status, message }) => {
+                assert_eq!(status, 500);
+                assert_eq!(message, "db down");
+            }
+            other => panic!("expected a server error, got {other:?}"),
+        }
+        assert_eq!(seen.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn error_field_in_a_200_is_a_client_error() {
+        let (url, _seen) = mock_server(vec![Reply::Status(200, r#"{"error":"Bad password"}"#)]);
+        let api = client(url, None, REQUEST_TIMEOUT);
+
+        match api.request::<serde_json::Value, _>(Method::POST, "/api/login", Some(&())) {
+            Err(ApiError::Client { status, message }) => {
+                assert_eq!(status, 200);
+                assert_eq!(message, "Bad password");
+            }
+            other => panic!("expected a client error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unauthorized_refreshes_and_retries_once() {
+        let (url, seen) = mock_server(vec![
+            Reply::Status(401, ""),
+            Reply::Status(200, r#"{"access_token":"new","expires_in":600}"#),
+            Reply::Status(200, r#"{"ok":true}"#),
+        ]);
+        let api = client(url, Some(session("old")), REQUEST_TIMEOUT);
+
+        let value: serde_json::Value = api
+            .authed_request::<_, ()>(Method::GET, "/api/session", None)
+            .unwrap();
+        assert_eq!(value["ok"], true);
+
+        let seen: Vec<Seen> = seen.try_iter().collect();
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[0].bearer.as_deref(), Some("old"));
+        assert_eq!(
+            (seen[1].method.as_str(), seen[1].path.as_str()),
+            ("POST", "/api/refresh")
+        );
+        assert_eq!(seen[2].bearer.as_deref(), Some("new"));
+
+        let stored = api.session.lock().unwrap().clone().unwrap();
+        assert_eq!(stored.access_token, "new");
+        assert_eq!(stored.refresh_token, "refresh");
+    }
+
+    #[test]
+    fn rejected_refresh_expires_the_session() {
+        let (url, _seen) = mock_server(vec![Reply::Status(401, ""), Reply::Status(401, "")]);
+        let api = client(url, Some(session("old")), REQUEST_TIMEOUT);
+
+        let result = api.authed_request::<serde_json::Value, ()>(Method::GET, "/api/session", None);
+        assert!(matches!(result, Err(ApiError::SessionExpired)));
+        assert!(!api.has_session());
+    }
+
+    #[test]
+    fn slow_server_times_out() {
+        let (url, _seen) = mock_server(vec![Reply::Hang]);
+        let api = client(url, None, Duration::from_millis(300));
+
+        let result = api.request::<serde_json::Value, _>(Method::POST, "/api/x", Some(&()));
+        assert!(matches!(result, Err(ApiError::Timeout)), "{result:?}");
+    }
+
+    #[test]
+    fn base_url_must_be_https() {
+        assert_eq!(
+            normalize_base_url("https://api.example.com/").unwrap(),
+            "https://api.example.com"
+        );
+        assert!(normalize_base_url("http://api.example.com").is_err());
+        assert!(normalize_base_url("not a url").is_err());
+    }
+
+    #[test]
+    fn long_server_messages_are_cut() {
+        let long = "x".repeat(MAX_MESSAGE_LEN + 50);
+        let message = server_message(&format!(r#"{{"error":"{long}"}}"#));
+        assert_eq!(message.len(), MAX_MESSAGE_LEN + 3);
+        assert!(message.ends_with("..."));
+    }
+
+    #[test]
+    fn matching_pin_connects() {
+        let api = pinned_client(
+            tls_server(1),
+            "sha256/QQxkCbFMCEldz7xYrlBqn3wUpco98O5E1GOzs57CHZg=",
+        );
+        let value: serde_json::Value = api.request(Method::POST, "/api/x", Some(&())).unwrap();
+        assert_eq!(value["ok"], true);
+    }
+
+    #[test]
+    fn wrong_pin_is_a_pin_mismatch() {
+        let api = pinned_client(
+            tls_server(1),
+            "sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+        );
+        match api.request::<serde_json::Value, _>(Method::POST, "/api/x", Some(&())) {
+            Err(ApiError::PinMismatch) => {}
+            other => panic!("expected a pin mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn sealing_covers_both_tokens() {
+        fn mark(token: &str) -> Result<String, String> {
+            Ok(format!("sealed:{token}"))
+        }
+        fn refuse(_: &str) -> Result<String, String> {
+            Err("no key".to_string())
+        }
+
+        let session = AuthSession {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: Some(42),
+        };
+        let sealed = session.map_tokens(mark).unwrap();
+        assert_eq!(sealed.access_token, "sealed:access");
+        assert_eq!(sealed.refresh_token, "sealed:refresh");
+        assert_eq!(sealed.expires_at, Some(42));
+        assert!(session.map_tokens(refuse).is_err());
+    }
+}
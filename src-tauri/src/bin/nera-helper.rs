@@ -0,0 +1,17 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// `nera-helper`: privileged service for tunnel and firewall operations; see
+// `app_lib::helper`.
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(app_lib::helper::run(&args));
+}
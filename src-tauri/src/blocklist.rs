@@ -0,0 +1,516 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// DNS-level ad and tracker blocking for the local resolver.
+//
+// Lists are hosts files (`0.0.0.0 domain ...`) or plain domain lists, either
+// bundled into the binary or loaded from disk. Enabled lists are compiled into
+// one map from domain to owning list; a name is blocked if it or any parent
+// domain is in the map. Blocked names get NXDOMAIN or a null address.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{append_log, error::NeraError, load_settings, update_settings, AppSettings, VpnState};
+
+const BUNDLED: [(&str, &str, &str); 2] = [
+    ("bundled-ads", "Ads", include_str!("../blocklists/ads.txt")),
+    (
+        "bundled-trackers",
+        "Trackers",
+        include_str!("../blocklists/trackers.txt"),
+    ),
+];
+
+// Names hosts files map to themselves, never worth blocking
+const HOSTS_IGNORED: [&str; 5] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "0.0.0.0",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockMode {
+    /// Answer NXDOMAIN.
+    Nxdomain,
+    /// Answer A with 0.0.0.0 and AAAA with ::.
+    NullAddress,
+}
+
+impl Default for BlockMode {
+    fn default() -> Self {
+        BlockMode::Nxdomain
+    }
+}
+
+/// A list the user added from disk. Bundled lists aren't stored here; only
+/// whether they're turned off is (`disabled_blocklists`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CustomBlocklist {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BlocklistStatus {
+    pub id: String,
+    pub name: String,
+    pub path: Option<String>,
+    pub bundled: bool,
+    pub enabled: bool,
+    pub entries: usize,
+    pub hits: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DnsBlockingStatus {
+    pub enabled: bool,
+    pub mode: BlockMode,
+    pub total_hits: u64,
+    pub lists: Vec<BlocklistStatus>,
+}
+
+/// Domains from one list's text, lowercased and without trailing dots.
+pub fn parse_list(content: &str) -> Vec<String> {
+    let mut domains = Vec::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let first = match tokens.next() {
+            Some(t) => t,
+            None => continue,
+        };
+
+        // Hosts format: an address followed by one or more names
+        let names: Vec<&str> = if first.parse::<IpAddr>().is_ok() {
+            tokens.collect()
+        } else {
+            vec![first]
+        };
+
+        for name in names {
+            let name = name
+                .trim_start_matches("*.")
+                .trim_matches('.')
+                .to_ascii_lowercase();
+            if !name.is_empty() && !HOSTS_IGNORED.contains(&name.as_str()) {
+                domains.push(name);
+            }
+        }
+    }
+    domains
+}
+
+struct ListInfo {
+    id: String,
+    name: String,
+    path: Option<String>,
+    enabled: bool,
+    entries: usize,
+    error: Option<String>,
+}
+
+/// Compiled view of the enabled lists.
+struct Matcher {
+    // Domain -> index into `lists` of the first list that contains it
+    domains: HashMap<String, usize>,
+    lists: Vec<ListInfo>,
+}
+
+impl Matcher {
+    fn compile(settings: &AppSettings) -> Self {
+        let mut lists = Vec::new();
+        let mut sources: Vec<(ListInfo, Result<String, String>)> = Vec::new();
+
+        for (id, name, content) in BUNDLED.iter() {
+            sources.push((
+                ListInfo {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    path: None,
+                    enabled: !settings.disabled_blocklists.iter().any(|d| d == id),
+                    entries: 0,
+                    error: None,
+                },
+                Ok(content.to_string()),
+            ));
+        }
+        for custom in &settings.custom_blocklists {
+            let content = if custom.enabled {
+                fs::read_to_string(&custom.path)
+                    .map_err(|e| format!("Failed to read {}: {e}", custom.path))
+            } else {
+                Ok(String::new())
+            };
+            sources.push((
+                ListInfo {
+                    id: custom.id.clone(),
+                    name: custom.name.clone(),
+                    path: Some(custom.path.clone()),
+                    enabled: custom.enabled,
+                    entries: 0,
+                    error: None,
+                },
+                content,
+            ));
+        }
+
+        let mut domains = HashMap::new();
+        for (mut info, content) in sources {
+            let index = lists.len();
+            match content {
+                Ok(text) if info.enabled => {
+                    let parsed = parse_list(&text);
+                    info.entries = parsed.len();
+                    for domain in parsed {
+                        domains.entry(domain).or_insert(index);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    append_log(&format!("Blocklist: {e}")).ok();
+                    info.error = Some(e);
+                }
+            }
+            lists.push(info);
+        }
+
+        Matcher { domains, lists }
+    }
+
+    /// Index of the list blocking `name`, checking the name and then each parent.
+    fn lookup(&self, name: &str) -> Option<usize> {
+        let mut candidate = name.trim_end_matches('.');
+        loop {
+            if let Some(index) = self.domains.get(candidate) {
+                return Some(*index);
+            }
+            match candidate.find('.') {
+                Some(dot) => candidate = &candidate[dot + 1..],
+                None => return None,
+            }
+        }
+    }
+}
+
+/// Blocking state shared between the resolver and the commands. Hit counters
+/// survive recompiles; they're kept per list id for the app's lifetime.
+pub struct DnsFilter {
+    enabled: RwLock<bool>,
+    mode: RwLock<BlockMode>,
+    matcher: RwLock<Arc<Matcher>>,
+    hits: Mutex<BTreeMap<String, u64>>,
+}
+
+impl DnsFilter {
+    pub fn load() -> Self {
+        let settings = load_settings();
+        let matcher = Matcher::compile(&settings);
+        if settings.dns_blocking_enabled {
+            append_log(&format!(
+                "Blocklist: {} domains loaded",
+                matcher.domains.len()
+            ))
+            .ok();
+        }
+
+        DnsFilter {
+            enabled: RwLock::new(settings.dns_blocking_enabled),
+            mode: RwLock::new(settings.dns_block_mode),
+            matcher: RwLock::new(Arc::new(matcher)),
+            hits: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// An enabled filter blocking just `domains`, for the resolver's tests.
+    #[cfg(test)]
+    pub fn blocking(domains: &[&str], mode: BlockMode) -> Self {
+        DnsFilter {
+            enabled: RwLock::new(true),
+            mode: RwLock::new(mode),
+            matcher: RwLock::new(Arc::new(tests::matcher(domains))),
+            hits: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Re-reads settings and list files.
+    pub fn reload(&self) {
+        let settings = load_settings();
+        let matcher = Matcher::compile(&settings);
+        append_log(&format!(
+            "Blocklist: reloaded, {} domains",
+            matcher.domains.len()
+        ))
+        .ok();
+
+        *self.enabled.write().unwrap() = settings.dns_blocking_enabled;
+        *self.mode.write().unwrap() = settings.dns_block_mode;
+        *self.matcher.write().unwrap() = Arc::new(matcher);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.enabled.read().unwrap()
+    }
+
+    /// How to answer `name`, or `None` to resolve it normally. Counts the hit.
+    pub fn check(&self, name: &str) -> Option<BlockMode> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let matcher = self.matcher.read().unwrap().clone();
+        let index = matcher.lookup(name)?;
+
+        *self
+            .hits
+            .lock()
+            .unwrap()
+            .entry(matcher.lists[index].id.clone())
+            .or_insert(0) += 1;
+        Some(*self.mode.read().unwrap())
+    }
+
+    fn status(&self) -> DnsBlockingStatus {
+        let matcher = self.matcher.read().unwrap().clone();
+        let hits = self.hits.lock().unwrap();
+
+        let lists: Vec<BlocklistStatus> = matcher
+            .lists
+            .iter()
+            .map(|l| BlocklistStatus {
+                id: l.id.clone(),
+                name: l.name.clone(),
+                path: l.path.clone(),
+                bundled: l.path.is_none(),
+                enabled: l.enabled,
+                entries: l.entries,
+                hits: hits.get(&l.id).copied().unwrap_or(0),
+                error: l.error.clone(),
+            })
+            .collect();
+
+        DnsBlockingStatus {
+            enabled: self.is_enabled(),
+            mode: *self.mode.read().unwrap(),
+            total_hits: lists.iter().map(|l| l.hits).sum(),
+            lists,
+        }
+    }
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_dns_blocking(state: State<'_, VpnState>) -> DnsBlockingStatus {
+    state.dns_filter.status()
+}
+
+/// Turns blocking on or off and optionally changes how blocked names are answered.
+/// Applies immediately if the local resolver is running (blocking routes DNS
+/// through it); otherwise the resolver starts with the next connect.
+#[tauri::command]
+pub fn set_dns_blocking(
+    state: State<'_, VpnState>,
+    enabled: bool,
+    mode: Option<BlockMode>,
+) -> Result<DnsBlockingStatus, NeraError> {
+    let mode = update_settings(|settings| {
+        settings.dns_blocking_enabled = enabled;
+        if let Some(mode) = mode {
+            settings.dns_block_mode = mode;
+        }
+        settings.dns_block_mode
+    });
+
+    state.dns_filter.reload();
+    append_log(&format!("DNS blocking enabled: {enabled} ({mode:?})")).ok();
+    if enabled && *state.connected.lock().unwrap() && state.dns.lock().unwrap().is_none() {
+        append_log("DNS blocking: the resolver isn't running; reconnect to apply.").ok();
+    }
+    Ok(state.dns_filter.status())
+}
+
+#[tauri::command]
+pub fn set_blocklist_enabled(
+    state: State<'_, VpnState>,
+    id: String,
+    enabled: bool,
+) -> Result<DnsBlockingStatus, NeraError> {
+    update_settings(|settings| {
+        if BUNDLED.iter().any(|(bundled_id, _, _)| *bundled_id == id) {
+            settings.disabled_blocklists.retain(|d| *d != id);
+            if !enabled {
+                settings.disabled_blocklists.push(id.clone());
+            }
+            return Ok::<_, NeraError>(());
+        }
+        let custom = settings
+            .custom_blocklists
+            .iter_mut()
+            .find(|c| c.id == id)
+            .ok_or_else(|| NeraError::NotFound(format!("Unknown blocklist: {id}")))?;
+        custom.enabled = enabled;
+        Ok(())
+    })?;
+
+    state.dns_filter.reload();
+    Ok(state.dns_filter.status())
+}
+
+/// Adds a hosts or domain-list file from disk.
+#[tauri::command]
+pub fn add_blocklist(
+    state: State<'_, VpnState>,
+    path: String,
+    name: Option<String>,
+) -> Result<DnsBlockingStatus, NeraError> {
+    let content = fs::read_to_string(&path)
+        .map_err(|e| NeraError::InvalidInput(format!("Failed to read {path}: {e}")))?;
+    if parse_list(&content).is_empty() {
+        return Err(NeraError::InvalidInput(format!(
+            "No domains found in {path}"
+        )));
+    }
+
+    let name = name.unwrap_or_else(|| {
+        std::path::Path::new(&path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone())
+    });
+    update_settings(|settings| {
+        if settings.custom_blocklists.iter().any(|c| c.path == path) {
+            return Err(NeraError::InvalidInput(format!("{path} is already added.")));
+        }
+        settings.custom_blocklists.push(CustomBlocklist {
+            id: format!("{:016x}", rand::random::<u64>()),
+            name,
+            path: path.clone(),
+            enabled: true,
+        });
+        Ok(())
+    })?;
+
+    append_log(&format!("Blocklist added: {path}")).ok();
+    state.dns_filter.reload();
+    Ok(state.dns_filter.status())
+}
+
+#[tauri::command]
+pub fn remove_blocklist(
+    state: State<'_, VpnState>,
+    id: String,
+) -> Result<DnsBlockingStatus, NeraError> {
+    update_settings(|settings| {
+        let before = settings.custom_blocklists.len();
+        settings.custom_blocklists.retain(|c| c.id != id);
+        if settings.custom_blocklists.len() == before {
+            return Err(NeraError::NotFound(format!("Unknown blocklist: {id}")));
+        }
+        Ok(())
+    })?;
+
+    state.dns_filter.reload();
+    Ok(state.dns_filter.status())
+}
+
+/// Re-reads custom list files after they've been edited.
+#[tauri::command]
+pub fn reload_blocklists(state: State<'_, VpnState>) -> DnsBlockingStatus {
+    state.dns_filter.reload();
+    state.dns_filter.status()
+}
+
+#[tauri::command]
+pub fn reset_blocklist_stats(state: State<'_, VpnState>) {
+    state.dns_filter.hits.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn matcher(domains: &[&str]) -> Matcher {
+        Matcher {
+            domains: domains.iter().map(|d| (d.to_string(), 0)).collect(),
+            lists: vec![ListInfo {
+                id: "test".to_string(),
+                name: "Test".to_string(),
+                path: None,
+                enabled: true,
+                entries: domains.len(),
+                error: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn parses_hosts_and_domain_lists() {
+        let content = "\
+# comment
+0.0.0.0 ads.example.com tracker.example.net # trailing comment
+127.0.0.1 localhost
+::1 localhost
+Plain.Example.ORG.
+*.wild.example
+
+";
+        assert_eq!(
+            parse_list(content),
+            vec![
+                "ads.example.com",
+                "tracker.example.net",
+                "plain.example.org",
+                "wild.example",
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup_matches_name_and_parents() {
+        let m = matcher(&["example.com"]);
+        assert_eq!(m.lookup("example.com"), Some(0));
+        assert_eq!(m.lookup("ads.example.com."), Some(0));
+        assert_eq!(m.lookup("a.b.example.com"), Some(0));
+        assert_eq!(m.lookup("notexample.com"), None);
+        assert_eq!(m.lookup("com"), None);
+    }
+
+    #[test]
+    fn check_counts_hits_only_when_enabled() {
+        let filter = DnsFilter {
+            enabled: RwLock::new(false),
+            mode: RwLock::new(BlockMode::NullAddress),
+            matcher: RwLock::new(Arc::new(matcher(&["example.com"]))),
+            hits: Mutex::new(BTreeMap::new()),
+        };
+        assert_eq!(filter.check("ads.example.com"), None);
+
+        *filter.enabled.write().unwrap() = true;
+        assert_eq!(
+            filter.check("ads.example.com"),
+            Some(BlockMode::NullAddress)
+        );
+        assert_eq!(filter.check("example.org"), None);
+        assert_eq!(filter.hits.lock().unwrap().get("test"), Some(&1));
+    }
+}
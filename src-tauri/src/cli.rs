@@ -0,0 +1,356 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// The `nera` command line.
+//
+// Scripts the VPN or checks it over SSH. When the app is running, connect,
+// disconnect and kill switch changes go to it over the control socket.
+// Otherwise the CLI runs the app's own code with no window or tray; nothing
+// it starts outlives the command, so such a connection uses plain DNS and
+// UDP only (no local resolver or TCP relay).
+//
+// Exit codes: 0 ok, 1 failed, 2 bad usage, 3 not connected (`status`),
+// 4 WireGuard missing, 5 administrator rights needed, 6 sign-in needed.
+
+use std::fs;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    apply_kill_switch, connect_vpn_internal,
+    control::{self, RemoteError},
+    disconnect_vpn_internal,
+    error::NeraError,
+    load_settings, log_file_path, multihop,
+    provisioning::{self, ProvisioningState},
+    servers,
+    session_journal::DisconnectReason,
+    temp_conf_path, traffic, tunnel_interface_name, KillSwitchScope, VpnState,
+};
+
+const EXIT_OK: i32 = 0;
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NOT_CONNECTED: i32 = 3;
+const EXIT_WIREGUARD_MISSING: i32 = 4;
+const EXIT_ELEVATION_REQUIRED: i32 = 5;
+const EXIT_SIGN_IN_NEEDED: i32 = 6;
+
+const DEFAULT_LOG_LINES: usize = 50;
+
+const USAGE: &str = "\
+Usage: nera <command>
+
+Commands:
+  connect [server]       Connect (default: the server selected in the app)
+  disconnect             Disconnect
+  status [--json]        Show the connection; exits 3 when not connected
+  killswitch on|off      Turn the firewall kill switch on or off
+  servers                List servers
+  logs [--tail [N]]      Print the last N log lines (default 50)";
+
+#[derive(Serialize)]
+struct Status {
+    connected: bool,
+    /// Server the traffic leaves from.
+    server: Option<String>,
+    /// First hop of a multi-hop connection.
+    entry_server: Option<String>,
+    interface: Option<String>,
+    /// Unix time of the last handshake.
+    last_handshake: Option<u64>,
+    kill_switch_enabled: bool,
+    selected_server: String,
+    provisioning: ProvisioningState,
+}
+
+/// A running tunnel, found from WireGuard rather than app state.
+struct LiveTunnel {
+    /// Outer interface first, multi-hop inner interface second.
+    interfaces: Vec<String>,
+    servers: Vec<&'static servers::ServerInfo>,
+}
+
+/// Exit code for a `NeraError` code, local or from the app.
+fn exit_code(code: &str) -> i32 {
+    match code {
+        "wireguard_missing" => EXIT_WIREGUARD_MISSING,
+        "elevation_required" => EXIT_ELEVATION_REQUIRED,
+        "not_provisioned" | "api_unauthorized" => EXIT_SIGN_IN_NEEDED,
+        _ => EXIT_FAILED,
+    }
+}
+
+/// Exit code for the app's answer, printing what went wrong if anything did.
+fn answered(reply: Result<Value, RemoteError>, on_success: impl FnOnce()) -> i32 {
+    match reply {
+        Ok(_) => {
+            on_success();
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("nera: {e}");
+            exit_code(&e.code)
+        }
+    }
+}
+
+/// Runs `nera` with `args` (without the program name); returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["connect"] => connect(None),
+        ["connect", server] => connect(Some(server)),
+        ["disconnect"] => disconnect(),
+        ["status"] => Ok(status(false)),
+        ["status", "--json"] => Ok(status(true)),
+        ["killswitch", "on"] => killswitch(true),
+        ["killswitch", "off"] => killswitch(false),
+        ["servers"] => Ok(list_servers()),
+        ["logs"] | ["logs", "--tail"] => logs(DEFAULT_LOG_LINES),
+        ["logs", "--tail", n] => match n.parse() {
+            Ok(n) => logs(n),
+            Err(_) => return usage_error(&format!("Not a line count: {n}")),
+        },
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{USAGE}");
+            Ok(EXIT_OK)
+        }
+        [] => return usage_error("Missing command."),
+        [command, ..] => return usage_error(&format!("Unknown command or arguments: {command}")),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            e.log("nera CLI");
+            eprintln!("nera: {e}");
+            exit_code(e.code())
+        }
+    }
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("nera: {message}\n\n{USAGE}");
+    EXIT_USAGE
+}
+
+fn server_on(interface: &str) -> Option<&'static servers::ServerInfo> {
+    traffic::peer_public_keys(interface)
+        .iter()
+        .find_map(|peer| servers::SERVERS.iter().find(|s| s.public_key == peer))
+}
+
+fn live_tunnel() -> Option<LiveTunnel> {
+    let outer = tunnel_interface_name(&temp_conf_path().ok()?);
+    let outer_server = server_on(&outer)?;
+    let mut tunnel = LiveTunnel {
+        interfaces: vec![outer],
+        servers: vec![outer_server],
+    };
+
+    let hop = multihop::hop_conf_path()
+        .ok()
+        .map(|p| tunnel_interface_name(&p));
+    if let Some((hop, server)) = hop.and_then(|h| server_on(&h).map(|s| (h, s))) {
+        tunnel.interfaces.push(hop);
+        tunnel.servers.push(server);
+    }
+    Some(tunnel)
+}
+
+fn connect(server: Option<&str>) -> Result<i32, NeraError> {
+    let key = match server {
+        Some(servers::AUTO_SERVER_KEY) => servers::AUTO_SERVER_KEY.to_string(),
+        Some(key) => servers::find_server(key)
+            .map(|s| s.key.to_string())
+            .ok_or_else(|| {
+                NeraError::NotFound(format!("Unknown server: {key}. See `nera servers`."))
+            })?,
+        None => load_settings().selected_server,
+    };
+    if live_tunnel().is_some() {
+        return Err(NeraError::Busy(
+            "Already connected. Run `nera disconnect` first.".to_string(),
+        ));
+    }
+
+    if let Some(reply) = control::request("connect", json!({ "server": key })) {
+        return Ok(answered(reply, print_connected));
+    }
+
+    let state = VpnState::new(load_settings().kill_switch_enabled, true)?;
+    connect_vpn_internal(None, &state, Some(key))?;
+    print_connected();
+    Ok(EXIT_OK)
+}
+
+fn print_connected() {
+    match live_tunnel().and_then(|t| t.servers.last().copied()) {
+        Some(s) => println!("Connected to {} ({}).", s.label, s.key),
+        None => println!("Connected."),
+    }
+}
+
+fn disconnect() -> Result<i32, NeraError> {
+    if let Some(reply) = control::request("disconnect", Value::Null) {
+        return Ok(answered(reply, || println!("Disconnected.")));
+    }
+
+    let state = VpnState::new(load_settings().kill_switch_enabled, true)?;
+    disconnect_vpn_internal(None, &state, DisconnectReason::User)?;
+    println!("Disconnected.");
+    Ok(EXIT_OK)
+}
+
+fn status(json: bool) -> i32 {
+    let settings = load_settings();
+    let tunnel = live_tunnel();
+    let inner = tunnel.as_ref().and_then(|t| t.interfaces.last().cloned());
+    let status = Status {
+        connected: tunnel.is_some(),
+        server: tunnel
+            .as_ref()
+            .and_then(|t| t.servers.last())
+            .map(|s| s.key.to_string()),
+        entry_server: tunnel
+            .as_ref()
+            .filter(|t| t.servers.len() > 1)
+            .map(|t| t.servers[0].key.to_string()),
+        last_handshake: tunnel
+            .as_ref()
+            .and_then(|t| traffic::latest_handshake(&t.interfaces[0])),
+        interface: inner,
+        kill_switch_enabled: settings.kill_switch_enabled,
+        selected_server: settings.selected_server.clone(),
+        provisioning: provisioning::current(&settings),
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).unwrap_or_default()
+        );
+    } else {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match (&status.server, &status.entry_server) {
+            (Some(exit), Some(entry)) => println!("Connected:   yes ({entry} -> {exit})"),
+            (Some(server), None) => println!("Connected:   yes ({server})"),
+            _ => println!("Connected:   no"),
+        }
+        if let Some(interface) = &status.interface {
+            println!("Interface:   {interface}");
+        }
+        if let Some(at) = status.last_handshake {
+            let age = chrono::Utc::now().timestamp() - at as i64;
+            println!("Handshake:   {age}s ago");
+        }
+        println!("Kill switch: {}", on_off(status.kill_switch_enabled));
+        println!("Selected:    {}", status.selected_server);
+        println!("Device:      {:?}", status.provisioning);
+    }
+
+    if status.connected {
+        EXIT_OK
+    } else {
+        EXIT_NOT_CONNECTED
+    }
+}
+
+fn killswitch(enabled: bool) -> Result<i32, NeraError> {
+    let done = || println!("Kill switch {}.", if enabled { "on" } else { "off" });
+    if let Some(reply) = control::request("set_kill_switch", json!({ "enabled": enabled })) {
+        return Ok(answered(reply, done));
+    }
+
+    let state = VpnState::new(load_settings().kill_switch_enabled, true)?;
+
+    // With a tunnel up, scope to it like the app would, not to the whole catalog
+    if let Some(tunnel) = live_tunnel() {
+        *state.active_scope.lock().unwrap() = Some(KillSwitchScope {
+            endpoints: traffic::peer_endpoints(&tunnel.interfaces[0]),
+            interfaces: tunnel.interfaces,
+            relays: Vec::new(),
+        });
+    }
+
+    apply_kill_switch(&state, enabled)?;
+    done();
+    Ok(EXIT_OK)
+}
+
+fn list_servers() -> i32 {
+    let selected = load_settings().selected_server;
+    for server in servers::SERVERS {
+        let marker = if server.key == selected { "*" } else { " " };
+        println!(
+            "{marker} {:<12} {:<24} {}",
+            server.key, server.label, server.host
+        );
+    }
+    EXIT_OK
+}
+
+fn logs(lines: usize) -> Result<i32, NeraError> {
+    let path = log_file_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(_) => {
+            println!("No logs yet.");
+            return Ok(EXIT_OK);
+        }
+    };
+    let all: Vec<&str> = content.lines().collect();
+    for line in &all[all.len().saturating_sub(lines)..] {
+        println!("{line}");
+    }
+    Ok(EXIT_OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_with(args: &[&str]) -> i32 {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        run(&args)
+    }
+
+    #[test]
+    fn error_codes_map_to_exit_codes() {
+        assert_eq!(exit_code("wireguard_missing"), EXIT_WIREGUARD_MISSING);
+        assert_eq!(exit_code("elevation_required"), EXIT_ELEVATION_REQUIRED);
+        assert_eq!(exit_code("not_provisioned"), EXIT_SIGN_IN_NEEDED);
+        assert_eq!(exit_code("api_unauthorized"), EXIT_SIGN_IN_NEEDED);
+        assert_eq!(exit_code("tunnel_failed"), EXIT_FAILED);
+    }
+
+    #[test]
+    fn bad_arguments_are_usage_errors() {
+        assert_eq!(run_with(&[]), EXIT_USAGE);
+        assert_eq!(run_with(&["teleport"]), EXIT_USAGE);
+        assert_eq!(run_with(&["killswitch", "maybe"]), EXIT_USAGE);
+        assert_eq!(run_with(&["logs", "--tail", "many"]), EXIT_USAGE);
+        assert_eq!(run_with(&["status", "--xml"]), EXIT_USAGE);
+        assert_eq!(run_with(&["connect", "a", "b"]), EXIT_USAGE);
+    }
+
+    #[test]
+    fn help_succeeds() {
+        assert_eq!(run_with(&["help"]), EXIT_OK);
+        assert_eq!(run_with(&["--help"]), EXIT_OK);
+    }
+
+    #[test]
+    fn unknown_server_fails_before_connecting() {
+        assert_eq!(run_with(&["connect", "atlantis"]), EXIT_FAILED);
+    }
+}
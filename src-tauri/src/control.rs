@@ -0,0 +1,715 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Local control socket.
+//
+// The running app listens on a Unix domain socket (a named pipe on Windows)
+// so the CLI and other local tools can drive it, and so the kill switch can
+// still be turned off when the window is gone (recovery must not depend on
+// the frontend). Only the user running the app can use it: the socket sits
+// in an owner-only directory under $XDG_RUNTIME_DIR (next to the logs
+// without one), and the pipe is named after the user's SID, owned by it and
+// open to it alone. The CLI won't talk to a pipe another account created.
+// Requests longer than `MAX_LINE` end the connection.
+//
+// Protocol: JSON-RPC 2.0, one request and one response per line.
+//   status                             -> the same snapshot as `get_state_snapshot`
+//   connect { "server"?: string }      -> null
+//   disconnect                         -> null
+//   set_kill_switch { "enabled": bool } -> null
+// App failures use error code -32000 with the `NeraError` as `data`.
+
+// Only the transport is platform-specific
+#![cfg_attr(not(any(unix, windows)), allow(dead_code))]
+
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    append_log, connect_vpn_internal, disconnect_vpn_internal, error::NeraError, events,
+    load_settings, session_journal::DisconnectReason, set_kill_switch_internal, VpnState,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const APP_ERROR: i64 = -32000;
+
+// Connecting can try several ports before it answers
+#[cfg(unix)]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+// Far more than any request needs
+const MAX_LINE: u64 = 64 * 1024;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct ConnectParams {
+    #[serde(default)]
+    server: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KillSwitchParams {
+    enabled: bool,
+}
+
+pub(crate) struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    pub(crate) fn new(code: i64, message: String) -> Self {
+        RpcError {
+            code,
+            message,
+            data: None,
+        }
+    }
+
+    pub(crate) fn method_not_found(method: &str) -> Self {
+        RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {method}"))
+    }
+}
+
+impl From<NeraError> for RpcError {
+    fn from(e: NeraError) -> Self {
+        RpcError {
+            code: APP_ERROR,
+            message: e.message(),
+            data: serde_json::to_value(&e).ok(),
+        }
+    }
+}
+
+/// A failure reported by the app on the other end of the socket, as the
+/// `{code, message, details}` of its `NeraError`.
+#[derive(Debug, Deserialize)]
+pub struct RemoteError {
+    pub code: String,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<String>,
+}
+
+impl RemoteError {
+    pub(crate) fn internal(message: String) -> Self {
+        RemoteError {
+            code: "internal".to_string(),
+            message,
+            details: None,
+        }
+    }
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.details {
+            Some(details) => write!(f, "{} ({details})", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+pub(crate) fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {e}")))
+}
+
+fn dispatch(app: &AppHandle, method: &str, params: Value) -> Result<Value, RpcError> {
+    append_log(&format!("Control socket: {method}")).ok();
+    let state = app.state::<VpnState>();
+    match method {
+        "status" => Ok(serde_json::to_value(events::get_state_snapshot(state)).unwrap_or_default()),
+        "connect" => {
+            let params: ConnectParams = parse_params(params)?;
+            let server = params
+                .server
+                .unwrap_or_else(|| load_settings().selected_server);
+            connect_vpn_internal(Some(app), &state, Some(server))?;
+            Ok(Value::Null)
+        }
+        "disconnect" => {
+            disconnect_vpn_internal(Some(app), &state, DisconnectReason::User)?;
+            Ok(Value::Null)
+        }
+        "set_kill_switch" => {
+            let params: KillSwitchParams = parse_params(params)?;
+            set_kill_switch_internal(app, &state, params.enabled)?;
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::method_not_found(method)),
+    }
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+    let mut error = json!({ "code": e.code, "message": e.message });
+    if let Some(data) = e.data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Handles one request line with `dispatch`. Notifications (no `id`) get no
+/// response.
+pub(crate) fn handle_line(
+    line: &str,
+    dispatch: impl FnOnce(&str, Value) -> Result<Value, RpcError>,
+) -> Option<Value> {
+    let request = match serde_json::from_str::<Value>(line) {
+        Ok(value) => value,
+        Err(e) => {
+            let e = RpcError::new(PARSE_ERROR, format!("Parse error: {e}"));
+            return Some(error_response(Value::Null, e));
+        }
+    };
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(r) if r.jsonrpc == "2.0" => r,
+        _ => {
+            let e = RpcError::new(INVALID_REQUEST, "Invalid request".to_string());
+            return Some(error_response(Value::Null, e));
+        }
+    };
+
+    let result = dispatch(&request.method, request.params);
+    let id = request.id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, e),
+    })
+}
+
+/// Reads a response line from the app (or the helper).
+pub(crate) fn parse_response(line: &str) -> Result<Value, RemoteError> {
+    let mut response: Value = serde_json::from_str(line)
+        .map_err(|e| RemoteError::internal(format!("Bad response from the app: {e}")))?;
+    if let Some(error) = response.get_mut("error") {
+        let message = error["message"]
+            .as_str()
+            .unwrap_or("Request failed")
+            .to_string();
+        return Err(serde_json::from_value::<RemoteError>(error["data"].take())
+            .unwrap_or_else(|_| RemoteError::internal(message)));
+    }
+    Ok(response["result"].take())
+}
+
+/// Answers requests from one client until it hangs up.
+fn serve(app: &AppHandle, reader: impl Read, mut writer: impl Write) {
+    let mut reader = BufReader::new(reader);
+    loop {
+        let mut line = String::new();
+        match (&mut reader).take(MAX_LINE).read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if !line.ends_with('\n') && line.len() as u64 >= MAX_LINE {
+            let e = RpcError::new(INVALID_REQUEST, "Request too long".to_string());
+            let _ = writeln!(writer, "{}", error_response(Value::Null, e));
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(&line, |method, params| dispatch(app, method, params)) {
+            if writeln!(writer, "{response}").is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Sends one request to the app over `stream` and reads its answer.
+fn exchange<S: Read + Write + Copy>(
+    stream: S,
+    method: &str,
+    params: Value,
+) -> Result<Value, RemoteError> {
+    let lost = |e: std::io::Error| RemoteError::internal(format!("Lost the app: {e}"));
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let mut writer = stream;
+    writeln!(writer, "{request}").map_err(lost)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(lost)?;
+    parse_response(&line)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{
+        fs,
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        path::PathBuf,
+        thread,
+    };
+
+    use serde_json::Value;
+    use tauri::AppHandle;
+
+    use super::{exchange, serve, RemoteError, REQUEST_TIMEOUT};
+    use crate::{append_log, log_dir};
+
+    fn socket_path() -> Result<PathBuf, String> {
+        // The runtime dir is per user and private; the logs dir is the fallback
+        let dir = match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+            Some(runtime) => PathBuf::from(runtime).join("nera"),
+            None => log_dir()?.join("control"),
+        };
+        Ok(dir.join("nera.sock"))
+    }
+
+    fn bind() -> Result<UnixListener, String> {
+        let path = socket_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .and_then(|_| fs::set_permissions(dir, fs::Permissions::from_mode(0o700)))
+                .map_err(|e| format!("Failed to prepare {}: {e}", dir.display()))?;
+        }
+
+        // A live socket belongs to another instance; a dead one to a crashed one
+        if UnixStream::connect(&path).is_ok() {
+            return Err("another instance is already listening".to_string());
+        }
+        let _ = fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)
+            .map_err(|e| format!("Failed to bind {}: {e}", path.display()))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict {}: {e}", path.display()))?;
+        Ok(listener)
+    }
+
+    pub fn spawn_control_server(app: AppHandle) {
+        let listener = match bind() {
+            Ok(l) => l,
+            Err(e) => {
+                append_log(&format!("Control socket unavailable: {e}")).ok();
+                return;
+            }
+        };
+        append_log("Control socket listening.").ok();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let app = app.clone();
+                        thread::spawn(move || serve(&app, &stream, &stream));
+                    }
+                    Err(e) => {
+                        append_log(&format!("Control socket accept failed: {e}")).ok();
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn remove_socket() {
+        if let Ok(path) = socket_path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Sends a request to the running app. `None` if no app is listening.
+    pub fn request(method: &str, params: Value) -> Option<Result<Value, RemoteError>> {
+        let stream = UnixStream::connect(socket_path().ok()?).ok()?;
+        if let Err(e) = stream.set_read_timeout(Some(REQUEST_TIMEOUT)) {
+            return Some(Err(RemoteError::internal(format!("Lost the app: {e}"))));
+        }
+        Some(exchange(&stream, method, params))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{
+        ffi::c_void,
+        fs::{File, OpenOptions},
+        io, mem,
+        os::windows::io::{AsRawHandle, FromRawHandle},
+        ptr, slice, thread,
+    };
+
+    use serde_json::Value;
+    use tauri::AppHandle;
+    use windows_sys::Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, LocalFree, ERROR_PIPE_CONNECTED, HANDLE,
+            INVALID_HANDLE_VALUE,
+        },
+        Security::{
+            Authorization::{
+                ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+                GetSecurityInfo, SDDL_REVISION_1, SE_KERNEL_OBJECT,
+            },
+            EqualSid, GetTokenInformation, TokenUser, OWNER_SECURITY_INFORMATION,
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER,
+        },
+        Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+        System::{
+            Pipes::{
+                ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+            },
+            Threading::{GetCurrentProcess, OpenProcessToken},
+        },
+    };
+
+    use super::{exchange, serve, RemoteError};
+    use crate::append_log;
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Calls `f` with this process's user SID.
+    fn with_user_sid<T>(f: impl FnOnce(*mut c_void) -> T) -> io::Result<T> {
+        unsafe {
+            let mut token: HANDLE = 0;
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // u64s keep the TOKEN_USER aligned; a SID is at most 68 bytes
+            let mut buffer = [0u64; 32];
+            let mut len = 0;
+            let read = GetTokenInformation(
+                token,
+                TokenUser,
+                buffer.as_mut_ptr() as *mut c_void,
+                mem::size_of_val(&buffer) as u32,
+                &mut len,
+            );
+            let error = io::Error::last_os_error();
+            CloseHandle(token);
+            if read == 0 {
+                return Err(error);
+            }
+            let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+            Ok(f(user.User.Sid))
+        }
+    }
+
+    /// This process's user SID as text, e.g. `S-1-5-21-...`.
+    fn user_sid() -> io::Result<String> {
+        with_user_sid(|sid| unsafe {
+            let mut text = ptr::null_mut();
+            if ConvertSidToStringSidW(sid, &mut text) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let len = (0..).take_while(|&i| *text.add(i) != 0).count();
+            let sid = String::from_utf16_lossy(slice::from_raw_parts(text, len));
+            LocalFree(text as _);
+            Ok(sid)
+        })?
+    }
+
+    fn pipe_name(sid: &str) -> String {
+        format!(r"\\.\pipe\nera-control-{sid}")
+    }
+
+    /// True if this process's user owns `pipe`. Anyone can create a pipe
+    /// name first, but only its creator can own it.
+    fn owned_by_user(pipe: &File) -> bool {
+        unsafe {
+            let mut owner = ptr::null_mut();
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            let read = GetSecurityInfo(
+                pipe.as_raw_handle() as HANDLE,
+                SE_KERNEL_OBJECT,
+                OWNER_SECURITY_INFORMATION,
+                &mut owner,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut descriptor,
+            );
+            if read != 0 {
+                return false;
+            }
+            let same = with_user_sid(|sid| EqualSid(sid, owner) != 0).unwrap_or(false);
+            LocalFree(descriptor as _);
+            same
+        }
+    }
+
+    struct Listener {
+        name: Vec<u16>,
+        descriptor: PSECURITY_DESCRIPTOR,
+    }
+
+    // The descriptor is only read after it's built, and freed once on drop
+    unsafe impl Send for Listener {}
+
+    impl Listener {
+        /// Builds the pipe's security and creates its first instance.
+        fn bind() -> Result<(Listener, File), String> {
+            let sid = user_sid().map_err(|e| format!("Failed to read the user's SID: {e}"))?;
+            // Owned by and open to this user only; nothing inherited
+            let sddl = format!("O:{sid}D:P(A;;GA;;;{sid})");
+
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            let converted = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    wide(&sddl).as_ptr(),
+                    SDDL_REVISION_1,
+                    &mut descriptor,
+                    ptr::null_mut(),
+                )
+            };
+            if converted == 0 {
+                return Err(format!(
+                    "Failed to build the control pipe's ACL: {}",
+                    io::Error::last_os_error()
+                ));
+            }
+            let listener = Listener {
+                name: wide(&pipe_name(&sid)),
+                descriptor,
+            };
+            // First instance only, so nobody can have squatted on the name
+            let pipe = listener.instance(true).map_err(|e| {
+                format!("Failed to create the control pipe (another instance?): {e}")
+            })?;
+            Ok((listener, pipe))
+        }
+
+        fn instance(&self, first: bool) -> io::Result<File> {
+            let attributes = SECURITY_ATTRIBUTES {
+                nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: self.descriptor,
+                bInheritHandle: 0,
+            };
+            let mut open_mode = PIPE_ACCESS_DUPLEX;
+            if first {
+                open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+            }
+            let pipe = unsafe {
+                CreateNamedPipeW(
+                    self.name.as_ptr(),
+                    open_mode,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    4096,
+                    4096,
+                    0,
+                    &attributes,
+                )
+            };
+            if pipe == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(unsafe { File::from_raw_handle(pipe as _) })
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            unsafe { LocalFree(self.descriptor as _) };
+        }
+    }
+
+    /// Waits for a client on `pipe`.
+    fn accept(pipe: &File) -> io::Result<()> {
+        unsafe {
+            if ConnectNamedPipe(pipe.as_raw_handle() as HANDLE, ptr::null_mut()) != 0
+                || GetLastError() == ERROR_PIPE_CONNECTED
+            {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
+
+    pub fn spawn_control_server(app: AppHandle) {
+        let (listener, mut pipe) = match Listener::bind() {
+            Ok(bound) => bound,
+            Err(e) => {
+                append_log(&format!("Control socket unavailable: {e}")).ok();
+                return;
+            }
+        };
+        append_log("Control socket listening.").ok();
+
+        // One pipe instance per client; a fresh one waits for the next
+        thread::spawn(move || loop {
+            match accept(&pipe) {
+                Ok(()) => {
+                    let app = app.clone();
+                    thread::spawn(move || serve(&app, &pipe, &pipe));
+                }
+                Err(e) => {
+                    append_log(&format!("Control socket accept failed: {e}")).ok();
+                }
+            }
+            pipe = match listener.instance(false) {
+                Ok(p) => p,
+                Err(e) => {
+                    append_log(&format!("Control socket stopped: {e}")).ok();
+                    return;
+                }
+            };
+        });
+    }
+
+    // The pipe goes away with the process
+    pub fn remove_socket() {}
+
+    /// Sends a request to the running app. `None` if no app is listening.
+    pub fn request(method: &str, params: Value) -> Option<Result<Value, RemoteError>> {
+        let sid = user_sid().ok()?;
+        let pipe = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pipe_name(&sid))
+            .ok()?;
+        if !owned_by_user(&pipe) {
+            return Some(Err(RemoteError::internal(
+                "The control pipe belongs to another account; not using it.".to_string(),
+            )));
+        }
+        Some(exchange(&pipe, method, params))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use serde_json::Value;
+    use tauri::AppHandle;
+
+    use super::RemoteError;
+    use crate::append_log;
+
+    pub fn spawn_control_server(_app: AppHandle) {
+        append_log("Control socket is not available on this platform yet.").ok();
+    }
+
+    pub fn remove_socket() {}
+
+    pub fn request(_method: &str, _params: Value) -> Option<Result<Value, RemoteError>> {
+        None
+    }
+}
+
+pub use platform::{remove_socket, request, spawn_control_server};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo(method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "echo" => Ok(params),
+            "fail" => Err(NeraError::Busy("Busy connecting".to_string()).into()),
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    #[test]
+    fn requests_get_results() {
+        let response = handle_line(
+            r#"{"jsonrpc":"2.0","id":7,"method":"echo","params":{"a":1}}"#,
+            echo,
+        )
+        .unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["a"], 1);
+        assert!(response.get("error").is_none());
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        assert!(handle_line(r#"{"jsonrpc":"2.0","method":"echo"}"#, echo).is_none());
+    }
+
+    #[test]
+    fn malformed_requests_are_rejected() {
+        let parse = handle_line("{not json", echo).unwrap();
+        assert_eq!(parse["error"]["code"], PARSE_ERROR);
+        assert_eq!(parse["id"], Value::Null);
+
+        let version = handle_line(r#"{"jsonrpc":"1.0","id":1,"method":"echo"}"#, echo).unwrap();
+        assert_eq!(version["error"]["code"], INVALID_REQUEST);
+
+        let unknown = handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#, echo).unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn app_errors_round_trip() {
+        let response = handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"fail"}"#, echo).unwrap();
+        assert_eq!(response["error"]["code"], APP_ERROR);
+
+        let e = parse_response(&response.to_string()).unwrap_err();
+        assert_eq!(e.code, "busy");
+        assert_eq!(e.message, "Busy connecting");
+    }
+
+    #[test]
+    fn responses_parse() {
+        let ok = parse_response(r#"{"jsonrpc":"2.0","id":1,"result":{"connected":true}}"#);
+        assert_eq!(ok.unwrap()["connected"], true);
+
+        // Errors without app data keep their message
+        let plain = parse_response(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Unknown method: x"}}"#,
+        )
+        .unwrap_err();
+        assert_eq!(plain.code, "internal");
+        assert_eq!(plain.message, "Unknown method: x");
+
+        assert!(parse_response("garbage").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exchange_sends_one_request_and_reads_its_answer() {
+        use std::os::unix::net::UnixStream;
+
+        let (client, app) = UnixStream::pair().unwrap();
+        let fake_app = std::thread::spawn(move || {
+            let mut line = String::new();
+            BufReader::new(&app).read_line(&mut line).unwrap();
+            let response = handle_line(&line, echo).unwrap();
+            writeln!(&app, "{response}").unwrap();
+        });
+
+        let result = exchange(&client, "echo", json!({ "enabled": true })).unwrap();
+        assert_eq!(result["enabled"], true);
+        let e = exchange(&client, "fail", Value::Null);
+        fake_app.join().unwrap();
+        // The fake app hung up after one request
+        assert!(e.is_err());
+    }
+}
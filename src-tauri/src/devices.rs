@@ -0,0 +1,292 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Devices registered to the signed-in account.
+//
+// Each install registers its own WireGuard key, so an account collects one
+// device per install. The server's list is shown with this install's entry
+// marked by matching its public key against the one in settings.
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use sysinfo::System;
+use tauri::State;
+
+use crate::{
+    api_client::NeraApiClient, append_log, error::NeraError, load_settings, update_settings,
+    VpnState,
+};
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub public_key: String,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub last_seen: Option<String>,
+    /// This install. Filled in locally, never sent by the server.
+    #[serde(default)]
+    pub current: bool,
+}
+
+#[derive(Serialize)]
+struct RenameDeviceRequest<'a> {
+    name: &'a str,
+}
+
+/// Name this install registers under: the user's choice, or the host name.
+pub fn local_device_name() -> String {
+    load_settings()
+        .device_name
+        .or_else(System::host_name)
+        .unwrap_or_else(|| "Nera VPN device".to_string())
+}
+
+fn validate_name(name: &str) -> Result<String, NeraError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(NeraError::InvalidInput(
+            "Device name can't be empty.".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(NeraError::InvalidInput(format!(
+            "Device name is limited to {MAX_NAME_LEN} characters."
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// `/api/devices/{id}` with the id percent-encoded, so an id from the server
+/// can't reach another endpoint.
+fn device_path(id: &str) -> Result<String, NeraError> {
+    // The URL parser would resolve these rather than escape them
+    if matches!(id, "" | "." | "..") {
+        return Err(NeraError::InvalidInput(format!(
+            "Invalid device id: {id:?}"
+        )));
+    }
+    let mut url = reqwest::Url::parse("https://localhost/api/devices").unwrap();
+    url.path_segments_mut().unwrap().push(id);
+    Ok(url.path().to_string())
+}
+
+/// The account's devices, marking the one registered with `own_key`.
+fn fetch_devices(api: &NeraApiClient, own_key: &str) -> Result<Vec<Device>, NeraError> {
+    let mut devices: Vec<Device> =
+        api.authed_request::<_, ()>(Method::GET, "/api/devices", None)?;
+
+    for device in &mut devices {
+        device.current = !own_key.is_empty() && device.public_key == own_key;
+    }
+    Ok(devices)
+}
+
+fn rename(
+    api: &NeraApiClient,
+    own_key: &str,
+    id: &str,
+    name: String,
+) -> Result<Vec<Device>, NeraError> {
+    api.authed_request::<serde_json::Value, _>(
+        Method::PATCH,
+        &device_path(id)?,
+        Some(&RenameDeviceRequest { name: &name }),
+    )?;
+    append_log(&format!("Device {id} renamed to {name}")).ok();
+
+    let devices = fetch_devices(api, own_key)?;
+    // Keep the local name in step when renaming this install
+    if devices.iter().any(|d| d.id == id && d.current) {
+        update_settings(|settings| settings.device_name = Some(name));
+    }
+    Ok(devices)
+}
+
+fn revoke(api: &NeraApiClient, own_key: &str, id: &str) -> Result<Vec<Device>, NeraError> {
+    let devices = fetch_devices(api, own_key)?;
+    match devices.iter().find(|d| d.id == id) {
+        None => return Err(NeraError::NotFound(format!("Unknown device: {id}"))),
+        Some(d) if d.current => {
+            return Err(NeraError::InvalidInput(
+                "This is the current device. Sign out to remove it.".to_string(),
+            ))
+        }
+        Some(_) => {}
+    }
+
+    api.authed_request::<serde_json::Value, ()>(Method::DELETE, &device_path(id)?, None)?;
+    append_log(&format!("Device {id} revoked")).ok();
+
+    fetch_devices(api, own_key)
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub async fn list_devices(state: State<'_, VpnState>) -> Result<Vec<Device>, NeraError> {
+    let api = state.api.clone();
+    tauri::async_runtime::spawn_blocking(move || fetch_devices(&api, &load_settings().public_key))
+        .await
+        .map_err(|e| NeraError::Internal(format!("Loading devices failed: {e}")))?
+}
+
+#[tauri::command]
+pub async fn rename_device(
+    state: State<'_, VpnState>,
+    id: String,
+    name: String,
+) -> Result<Vec<Device>, NeraError> {
+    let name = validate_name(&name)?;
+    let api = state.api.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        rename(&api, &load_settings().public_key, &id, name)
+    })
+    .await
+    .map_err(|e| NeraError::Internal(format!("Renaming device failed: {e}")))?
+}
+
+/// Removes another device from the account; its key stops working.
+#[tauri::command]
+pub async fn revoke_device(
+    state: State<'_, VpnState>,
+    id: String,
+) -> Result<Vec<Device>, NeraError> {
+    let api = state.api.clone();
+    tauri::async_runtime::spawn_blocking(move || revoke(&api, &load_settings().public_key, &id))
+        .await
+        .map_err(|e| NeraError::Internal(format!("Revoking device failed: {e}")))?
+}
+
+#[tauri::command]
+pub fn get_device_name() -> String {
+    local_device_name()
+}
+
+/// Sets the name sent the next time this install registers or signs in.
+#[tauri::command]
+pub fn set_device_name(name: Option<String>) -> Result<String, NeraError> {
+    let name = match name {
+        Some(n) => Some(validate_name(&n)?),
+        None => None,
+    };
+
+    update_settings(|settings| settings.device_name = name);
+    Ok(local_device_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::tests::{client, mock_server, session, Reply, Seen};
+    use std::time::Duration;
+
+    const DEVICES: &str = r#"[
+        {"id": "a/b?c", "name": "Laptop", "public_key": "k1"},
+        {"id": "d2", "name": "Phone", "public_key": "k2", "ip": "10.0.0.3"}
+    ]"#;
+
+    fn requests(seen: &std::sync::mpsc::Receiver<Seen>) -> Vec<(String, String)> {
+        seen.try_iter().map(|s| (s.method, s.path)).collect()
+    }
+
+    fn api(replies: Vec<Reply>) -> (NeraApiClient, std::sync::mpsc::Receiver<Seen>) {
+        let (url, seen) = mock_server(replies);
+        (
+            client(url, Some(session("token")), Duration::from_secs(5)),
+            seen,
+        )
+    }
+
+    #[test]
+    fn ids_are_a_single_escaped_path_segment() {
+        assert_eq!(device_path("d2").unwrap(), "/api/devices/d2");
+        assert_eq!(
+            device_path("a/b?c#d").unwrap(),
+            "/api/devices/a%2Fb%3Fc%23d"
+        );
+        assert_eq!(device_path("../keys").unwrap(), "/api/devices/..%2Fkeys");
+        for id in ["", ".", ".."] {
+            assert!(device_path(id).is_err(), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn list_marks_this_install() {
+        let (api, seen) = api(vec![Reply::Status(200, DEVICES)]);
+
+        let devices = fetch_devices(&api, "k2").unwrap();
+        assert_eq!(
+            devices.iter().map(|d| d.current).collect::<Vec<_>>(),
+            [false, true]
+        );
+        assert_eq!(devices[1].ip.as_deref(), Some("10.0.0.3"));
+        let seen: Vec<Seen> = seen.try_iter().collect();
+        assert_eq!(seen[0].path, "/api/devices");
+        assert_eq!(seen[0].bearer.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn rename_patches_the_escaped_id_then_reloads() {
+        let (api, seen) = api(vec![Reply::Status(200, "{}"), Reply::Status(200, DEVICES)]);
+
+        let devices = rename(&api, "k2", "a/b?c", "Work laptop".to_string()).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(
+            requests(&seen),
+            [
+                ("PATCH".to_string(), "/api/devices/a%2Fb%3Fc".to_string()),
+                ("GET".to_string(), "/api/devices".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn revoke_deletes_another_device() {
+        let (api, seen) = api(vec![
+            Reply::Status(200, DEVICES),
+            Reply::Status(200, "{}"),
+            Reply::Status(200, DEVICES),
+        ]);
+
+        revoke(&api, "k2", "a/b?c").unwrap();
+        assert_eq!(
+            requests(&seen)[1],
+            ("DELETE".to_string(), "/api/devices/a%2Fb%3Fc".to_string())
+        );
+    }
+
+    #[test]
+    fn revoke_refuses_this_install_and_unknown_ids() {
+        let (api, seen) = api(vec![
+            Reply::Status(200, DEVICES),
+            Reply::Status(200, DEVICES),
+        ]);
+
+        let current = revoke(&api, "k2", "d2");
+        assert!(
+            matches!(current, Err(NeraError::InvalidInput(_))),
+            "{current:?}"
+        );
+        let unknown = revoke(&api, "k2", "nope");
+        assert!(
+            matches!(unknown, Err(NeraError::NotFound(_))),
+            "{unknown:?}"
+        );
+        // Only the two lookups; nothing was deleted
+        assert!(requests(&seen).iter().all(|(method, _)| method == "GET"));
+    }
+}
@@ -0,0 +1,936 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Local encrypted DNS stub resolver.
+//
+// When enabled, the tunnel's `DNS =` points at a UDP listener on 127.0.0.53 and
+// every query is forwarded over DNS-over-HTTPS or DNS-over-TLS to the profile's
+// upstreams. The tunnel routes everything, so those upstream connections go
+// through it like any other traffic. Upstreams carry a bootstrap IP: resolving
+// their hostnames through the system would loop back into this resolver.
+// Names on an enabled blocklist (see `blocklist`) are answered locally.
+// Queries are handled by a fixed pool of workers; when it falls behind, new
+// queries are dropped and the client retries. Stopping waits for the listener
+// and the workers, so the address is free again for the next connect.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{
+    append_log,
+    blocklist::{BlockMode, DnsFilter},
+    error::NeraError,
+    load_settings,
+    relay::{read_frame, write_frame},
+    update_settings, AppSettings, VpnState,
+};
+
+const LISTEN_IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 53);
+const LISTEN_PORT: u16 = 53;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MAX_MESSAGE: usize = 4096;
+const MAX_CACHE_ENTRIES: usize = 4096;
+const WORKERS: usize = 8;
+const QUEUE_DEPTH: usize = 128;
+// Cap on how long anything stays cached, and the TTL for negative answers
+// that don't carry an SOA
+const MAX_CACHE_TTL: u32 = 3600;
+const NEGATIVE_TTL: u32 = 60;
+
+const BLOCKED_TTL: u32 = 60;
+
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const QTYPE_A: u16 = 1;
+const QTYPE_AAAA: u16 = 28;
+// EDNS pseudo-record; its "TTL" holds flags
+const QTYPE_OPT: u16 = 41;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "protocol", rename_all = "snake_case")]
+pub enum DnsUpstream {
+    /// RFC 8484 POST to `url`, connecting to `ip`.
+    Doh { url: String, ip: IpAddr },
+    /// RFC 7858 to `ip:port`, verifying the certificate against `host`.
+    Dot {
+        host: String,
+        ip: IpAddr,
+        #[serde(default = "default_dot_port")]
+        port: u16,
+    },
+}
+
+fn default_dot_port() -> u16 {
+    853
+}
+
+impl DnsUpstream {
+    fn label(&self) -> String {
+        match self {
+            DnsUpstream::Doh { url, .. } => url.clone(),
+            DnsUpstream::Dot { host, ip, port } => format!("tls://{host}@{ip}:{port}"),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            DnsUpstream::Doh { url, .. } => {
+                let parsed =
+                    reqwest::Url::parse(url).map_err(|e| format!("Invalid DoH URL {url}: {e}"))?;
+                if parsed.scheme() != "https" || parsed.host_str().is_none() {
+                    return Err(format!("DoH URL must be https with a host: {url}"));
+                }
+                Ok(())
+            }
+            DnsUpstream::Dot { host, .. } if host.trim().is_empty() => {
+                Err("DoT upstream needs a host name for certificate checks.".to_string())
+            }
+            DnsUpstream::Dot { .. } => Ok(()),
+        }
+    }
+}
+
+pub fn listen_addr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(LISTEN_IP), LISTEN_PORT)
+}
+
+/// The plain resolver in the config templates, used when ours isn't running.
+pub const PLAIN_DNS: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+
+/// Used for profiles without their own upstream list.
+pub fn default_upstreams() -> Vec<DnsUpstream> {
+    vec![
+        DnsUpstream::Doh {
+            url: "https://cloudflare-dns.com/dns-query".to_string(),
+            ip: IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+        },
+        DnsUpstream::Dot {
+            host: "cloudflare-dns.com".to_string(),
+            ip: IpAddr::V4(Ipv4Addr::new(1, 0, 0, 1)),
+            port: 853,
+        },
+        DnsUpstream::Doh {
+            url: "https://dns.quad9.net/dns-query".to_string(),
+            ip: IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9)),
+        },
+    ]
+}
+
+pub fn upstreams_for(settings: &AppSettings, server_key: &str) -> Vec<DnsUpstream> {
+    settings
+        .dns_upstreams
+        .get(server_key)
+        .filter(|list| !list.is_empty())
+        .cloned()
+        .unwrap_or_else(default_upstreams)
+}
+
+// --- Wire format ---
+
+/// Position just past the (possibly compressed) name starting at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xC0 == 0xC0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+/// Lowercased query name plus type and class of the first question, and the
+/// offset where the question ends.
+fn parse_question(msg: &[u8]) -> Option<(String, u16, u16, usize)> {
+    if msg.len() < 12 || u16::from_be_bytes([msg[4], msg[5]]) == 0 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = 12;
+    loop {
+        let len = *msg.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression can't appear in a question we're asked
+        if len & 0xC0 != 0 {
+            return None;
+        }
+        let label = msg.get(pos..pos + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += len;
+    }
+
+    let qtype = u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]);
+    let qclass = u16::from_be_bytes([*msg.get(pos + 2)?, *msg.get(pos + 3)?]);
+    Some((labels.join("."), qtype, qclass, pos + 4))
+}
+
+/// Smallest TTL across the answer and authority sections.
+fn min_ttl(msg: &[u8], question_end: usize) -> Option<u32> {
+    let ancount = u16::from_be_bytes([msg[6], msg[7]]) as usize;
+    let nscount = u16::from_be_bytes([msg[8], msg[9]]) as usize;
+
+    let mut pos = question_end;
+    let mut ttl: Option<u32> = None;
+    for _ in 0..ancount + nscount {
+        pos = skip_name(msg, pos)?;
+        let header = msg.get(pos..pos + 10)?;
+        let record_ttl = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+        ttl = Some(ttl.map_or(record_ttl, |t| t.min(record_ttl)));
+        pos += 10 + rdlen;
+    }
+    ttl
+}
+
+/// Counts `age` seconds off every record TTL, for answers served from cache.
+fn age_ttls(msg: &mut [u8], question_end: usize, age: u32) -> Option<()> {
+    let count = [6, 8, 10]
+        .iter()
+        .map(|&i| u16::from_be_bytes([msg[i], msg[i + 1]]) as usize)
+        .sum::<usize>();
+
+    let mut pos = question_end;
+    for _ in 0..count {
+        pos = skip_name(msg, pos)?;
+        let header = msg.get_mut(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        if rtype != QTYPE_OPT {
+            let ttl = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            header[4..8].copy_from_slice(&ttl.saturating_sub(age).to_be_bytes());
+        }
+        let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10 + rdlen;
+    }
+    Some(())
+}
+
+fn rcode(msg: &[u8]) -> u8 {
+    msg[3] & 0x0F
+}
+
+/// Answer to `query` carrying no records, just `rcode`.
+fn empty_response(query: &[u8], question_end: usize, rcode: u8) -> Vec<u8> {
+    let mut out = query[..question_end].to_vec();
+    // QR, keep opcode and RD
+    out[2] = 0x80 | (query[2] & 0x79);
+    // RA + rcode
+    out[3] = 0x80 | (rcode & 0x0F);
+    // One question, nothing else
+    out[4..12].copy_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    out
+}
+
+/// Answer for a blocked name: NXDOMAIN, or a null A/AAAA record (other types
+/// get an empty NOERROR so clients don't retry elsewhere).
+fn blocked_response(query: &[u8], question_end: usize, qtype: u16, mode: BlockMode) -> Vec<u8> {
+    let rdata: &[u8] = match (mode, qtype) {
+        (BlockMode::Nxdomain, _) => return empty_response(query, question_end, RCODE_NXDOMAIN),
+        (BlockMode::NullAddress, QTYPE_A) => &[0; 4],
+        (BlockMode::NullAddress, QTYPE_AAAA) => &[0; 16],
+        (BlockMode::NullAddress, _) => return empty_response(query, question_end, 0),
+    };
+
+    let mut out = empty_response(query, question_end, 0);
+    // ANCOUNT = 1
+    out[7] = 1;
+    // Name is a pointer back to the question at offset 12
+    out.extend_from_slice(&[0xC0, 0x0C]);
+    out.extend_from_slice(&qtype.to_be_bytes());
+    out.extend_from_slice(&query[question_end - 2..question_end]);
+    out.extend_from_slice(&BLOCKED_TTL.to_be_bytes());
+    out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(rdata);
+    out
+}
+
+// --- Upstream transport ---
+
+struct Upstreams {
+    list: Vec<DnsUpstream>,
+    https: reqwest::blocking::Client,
+    tls: native_tls::TlsConnector,
+}
+
+impl Upstreams {
+    fn new(list: Vec<DnsUpstream>) -> Result<Self, String> {
+        Self::with_roots(list, &[])
+    }
+
+    /// Trusts the PEM certificates in `roots` on top of the system's.
+    fn with_roots(list: Vec<DnsUpstream>, roots: &[&[u8]]) -> Result<Self, String> {
+        let mut tls = native_tls::TlsConnector::builder();
+        let mut builder = reqwest::blocking::Client::builder().timeout(UPSTREAM_TIMEOUT);
+        for pem in roots {
+            let invalid = |e: &dyn std::fmt::Display| format!("Invalid root certificate: {e}");
+            tls.add_root_certificate(
+                native_tls::Certificate::from_pem(pem).map_err(|e| invalid(&e))?,
+            );
+            builder = builder.add_root_certificate(
+                reqwest::Certificate::from_pem(pem).map_err(|e| invalid(&e))?,
+            );
+        }
+        let tls = tls
+            .build()
+            .map_err(|e| format!("Failed to initialise TLS: {e}"))?;
+
+        // Pin DoH hostnames to their bootstrap IPs
+        for upstream in &list {
+            if let DnsUpstream::Doh { url, ip } = upstream {
+                if let Ok(parsed) = reqwest::Url::parse(url) {
+                    if let Some(host) = parsed.host_str() {
+                        let port = parsed.port_or_known_default().unwrap_or(443);
+                        builder = builder.resolve(host, SocketAddr::new(*ip, port));
+                    }
+                }
+            }
+        }
+
+        Ok(Upstreams {
+            list,
+            https: builder
+                .build()
+                .map_err(|e| format!("Failed to build DoH client: {e}"))?,
+            tls,
+        })
+    }
+
+    fn query_doh(&self, url: &str, query: &[u8]) -> Result<Vec<u8>, String> {
+        let response = self
+            .https
+            .post(url)
+            .header("content-type", "application/dns-message")
+            .header("accept", "application/dns-message")
+            .body(query.to_vec())
+            .send()
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        response
+            .bytes()
+            .map(|b| b.to_vec())
+            .map_err(|e| e.to_string())
+    }
+
+    fn query_dot(&self, host: &str, addr: SocketAddr, query: &[u8]) -> Result<Vec<u8>, String> {
+        let tcp = TcpStream::connect_timeout(&addr, UPSTREAM_TIMEOUT).map_err(|e| e.to_string())?;
+        tcp.set_read_timeout(Some(UPSTREAM_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        tcp.set_write_timeout(Some(UPSTREAM_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        let mut tls = self.tls.connect(host, tcp).map_err(|e| e.to_string())?;
+
+        // DNS over TCP uses the same 2-byte length framing as the relay
+        write_frame(&mut tls, query).map_err(|e| e.to_string())?;
+        let mut buf = vec![0u8; u16::MAX as usize];
+        let len = read_frame(&mut tls, &mut buf).map_err(|e| e.to_string())?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Tries each upstream in order until one gives a well-formed answer.
+    fn resolve(&self, query: &[u8]) -> Result<Vec<u8>, String> {
+        let mut last_error = "no upstreams configured".to_string();
+        for upstream in &self.list {
+            let result = match upstream {
+                DnsUpstream::Doh { url, .. } => self.query_doh(url, query),
+                DnsUpstream::Dot { host, ip, port } => {
+                    self.query_dot(host, SocketAddr::new(*ip, *port), query)
+                }
+            };
+            match result {
+                Ok(answer) if answer.len() >= 12 && answer[..2] == query[..2] => return Ok(answer),
+                Ok(_) => last_error = format!("{}: malformed answer", upstream.label()),
+                Err(e) => last_error = format!("{}: {e}", upstream.label()),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+// --- Cache ---
+
+struct CacheEntry {
+    response: Vec<u8>,
+    question_end: usize,
+    stored: Instant,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct DnsCache {
+    entries: HashMap<(String, u16, u16), CacheEntry>,
+}
+
+impl DnsCache {
+    /// Cached answer re-labelled with the new query's transaction id, its
+    /// TTLs reduced by the time it has spent in the cache.
+    fn get(&mut self, key: &(String, u16, u16), id: [u8; 2]) -> Option<Vec<u8>> {
+        let fresh = self.entries.get(key)?.expires > Instant::now();
+        if !fresh {
+            self.entries.remove(key);
+            return None;
+        }
+        let entry = &self.entries[key];
+        let mut response = entry.response.clone();
+        response[..2].copy_from_slice(&id);
+        let age = entry.stored.elapsed().as_secs().min(u32::MAX as u64) as u32;
+        age_ttls(&mut response, entry.question_end, age)?;
+        Some(response)
+    }
+
+    fn insert(&mut self, key: (String, u16, u16), response: &[u8], question_end: usize) {
+        let ttl = match rcode(response) {
+            0 => min_ttl(response, question_end),
+            RCODE_NXDOMAIN => Some(min_ttl(response, question_end).unwrap_or(NEGATIVE_TTL)),
+            // Don't cache failures
+            _ => None,
+        };
+        let ttl = match ttl {
+            Some(ttl) if ttl > 0 => ttl.min(MAX_CACHE_TTL),
+            _ => return,
+        };
+
+        if self.entries.len() >= MAX_CACHE_ENTRIES {
+            let now = Instant::now();
+            self.entries.retain(|_, e| e.expires > now);
+            if self.entries.len() >= MAX_CACHE_ENTRIES {
+                self.entries.clear();
+            }
+        }
+        let now = Instant::now();
+        self.entries.insert(
+            key,
+            CacheEntry {
+                response: response.to_vec(),
+                question_end,
+                stored: now,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+    }
+}
+
+// --- Resolver ---
+
+pub struct DnsResolver {
+    listen: SocketAddr,
+    cache: Arc<Mutex<DnsCache>>,
+    shutdown: Arc<AtomicBool>,
+    // The listener and workers; each holds the socket
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+fn handle_query(
+    query: &[u8],
+    upstreams: &Upstreams,
+    cache: &Mutex<DnsCache>,
+    filter: &DnsFilter,
+) -> Option<Vec<u8>> {
+    let (name, qtype, qclass, question_end) = parse_question(query)?;
+
+    if let Some(mode) = filter.check(&name) {
+        return Some(blocked_response(query, question_end, qtype, mode));
+    }
+
+    let key = (name, qtype, qclass);
+
+    if let Some(hit) = cache.lock().unwrap().get(&key, [query[0], query[1]]) {
+        return Some(hit);
+    }
+
+    match upstreams.resolve(query) {
+        Ok(answer) => {
+            cache.lock().unwrap().insert(key, &answer, question_end);
+            Some(answer)
+        }
+        Err(e) => {
+            append_log(&format!("DNS: {} failed: {e}", key.0)).ok();
+            Some(empty_response(query, question_end, RCODE_SERVFAIL))
+        }
+    }
+}
+
+impl DnsResolver {
+    /// Binds `listen` and starts answering queries from `upstreams`, minus
+    /// whatever `filter` blocks.
+    pub fn start(
+        listen: SocketAddr,
+        upstreams: Vec<DnsUpstream>,
+        filter: Arc<DnsFilter>,
+    ) -> Result<Self, String> {
+        // Built here so a broken TLS setup fails the start and the caller
+        // can fall back to plain DNS
+        Self::start_with(listen, Upstreams::new(upstreams)?, filter)
+    }
+
+    fn start_with(
+        listen: SocketAddr,
+        upstreams: Upstreams,
+        filter: Arc<DnsFilter>,
+    ) -> Result<Self, String> {
+        let upstreams = Arc::new(upstreams);
+        let udp = UdpSocket::bind(listen)
+            .map_err(|e| format!("Failed to bind DNS resolver on {listen}: {e}"))?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|e| e.to_string())?;
+        let listen = udp.local_addr().map_err(|e| e.to_string())?;
+
+        let labels: Vec<String> = upstreams.list.iter().map(DnsUpstream::label).collect();
+        let cache = Arc::new(Mutex::new(DnsCache::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let stop = shutdown.clone();
+
+        let (queue, jobs) = mpsc::sync_channel::<(Vec<u8>, SocketAddr)>(QUEUE_DEPTH);
+        let jobs = Arc::new(Mutex::new(jobs));
+        let mut threads = Vec::with_capacity(WORKERS + 1);
+        for _ in 0..WORKERS {
+            let jobs = jobs.clone();
+            let socket = udp.try_clone().map_err(|e| e.to_string())?;
+            let upstreams = upstreams.clone();
+            let cache = cache.clone();
+            let filter = filter.clone();
+            let stop = shutdown.clone();
+            threads.push(thread::spawn(move || loop {
+                // Ends once the listener is gone; queued queries are dropped
+                // when stopping
+                let job = jobs.lock().unwrap().recv();
+                let (query, from) = match job {
+                    Ok(_) if stop.load(Ordering::Relaxed) => continue,
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                if let Some(answer) = handle_query(&query, &upstreams, &cache, &filter) {
+                    let _ = socket.send_to(&answer, from);
+                }
+            }));
+        }
+
+        threads.push(thread::spawn(move || {
+            let mut buf = [0u8; MAX_MESSAGE];
+            while !stop.load(Ordering::Relaxed) {
+                let (len, from) = match udp.recv_from(&mut buf) {
+                    Ok(r) => r,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    // Windows reports ICMP port unreachable from a previous send here
+                    Err(_) => continue,
+                };
+
+                // A full queue means the upstreams are slow; drop the query
+                // rather than pile up work
+                let _ = queue.try_send((buf[..len].to_vec(), from));
+            }
+        }));
+
+        append_log(&format!(
+            "DNS: resolver listening on {listen}, upstreams: {}",
+            labels.join(", ")
+        ))
+        .ok();
+
+        Ok(DnsResolver {
+            listen,
+            cache,
+            shutdown,
+            threads: Mutex::new(threads),
+        })
+    }
+
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen
+    }
+
+    pub fn flush(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    /// Returns once the listen address is free. Queries in flight upstream
+    /// are finished first.
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // Wakes the listener instead of waiting out its poll
+        if let Ok(waker) = UdpSocket::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)) {
+            let _ = waker.send_to(&[], self.listen);
+        }
+        for thread in self.threads.lock().unwrap().drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DnsResolver {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// --- Tauri Commands ---
+
+#[derive(Serialize)]
+pub struct DnsSettings {
+    pub enabled: bool,
+    pub running: bool,
+    pub listen: String,
+    pub defaults: Vec<DnsUpstream>,
+    pub profiles: BTreeMap<String, Vec<DnsUpstream>>,
+}
+
+#[tauri::command]
+pub fn get_dns_settings(state: State<'_, VpnState>) -> DnsSettings {
+    let settings = load_settings();
+    DnsSettings {
+        enabled: settings.dns_resolver_enabled,
+        running: state.dns.lock().unwrap().is_some(),
+        listen: listen_addr().to_string(),
+        defaults: default_upstreams(),
+        profiles: settings.dns_upstreams,
+    }
+}
+
+/// Takes effect on the next connect.
+#[tauri::command]
+pub fn set_dns_resolver_enabled(enabled: bool) -> Result<(), NeraError> {
+    update_settings(|settings| settings.dns_resolver_enabled = enabled);
+
+    append_log(&format!("Encrypted DNS resolver enabled: {enabled}")).ok();
+    Ok(())
+}
+
+/// Sets the upstreams for one profile (server key); `None` or an empty list
+/// goes back to the defaults. Takes effect on the next connect.
+#[tauri::command]
+pub fn set_dns_upstreams(
+    server_key: String,
+    upstreams: Option<Vec<DnsUpstream>>,
+) -> Result<(), NeraError> {
+    let upstreams = upstreams.unwrap_or_default();
+    for upstream in &upstreams {
+        upstream.validate().map_err(NeraError::InvalidInput)?;
+    }
+
+    update_settings(|settings| {
+        if upstreams.is_empty() {
+            settings.dns_upstreams.remove(&server_key);
+        } else {
+            settings.dns_upstreams.insert(server_key.clone(), upstreams);
+        }
+    });
+
+    append_log(&format!("DNS upstreams updated for {server_key}")).ok();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn flush_dns_cache(state: State<'_, VpnState>) {
+    if let Some(resolver) = state.dns.lock().unwrap().as_ref() {
+        resolver.flush();
+        append_log("DNS cache flushed.").ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write as _},
+        net::TcpListener,
+        sync::atomic::AtomicUsize,
+    };
+
+    const TEST_CA: &[u8] = include_bytes!("../tests/fixtures/test-ca.pem");
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut msg = id.to_be_bytes().to_vec();
+        msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(label.as_bytes());
+        }
+        msg.push(0);
+        msg.extend_from_slice(&qtype.to_be_bytes());
+        msg.extend_from_slice(&1u16.to_be_bytes());
+        msg
+    }
+
+    /// NOERROR answer to `q` with one A record per TTL.
+    fn answer(q: &[u8], ttls: &[u32]) -> Vec<u8> {
+        let (_, _, _, end) = parse_question(q).unwrap();
+        let mut out = empty_response(q, end, 0);
+        out[7] = ttls.len() as u8;
+        for ttl in ttls {
+            out.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1]);
+            out.extend_from_slice(&ttl.to_be_bytes());
+            out.extend_from_slice(&[0, 4, 192, 0, 2, 1]);
+        }
+        out
+    }
+
+    fn ttls(msg: &[u8], question_end: usize) -> Vec<u32> {
+        let count = u16::from_be_bytes([msg[6], msg[7]]) as usize;
+        let mut pos = question_end;
+        let mut out = Vec::new();
+        for _ in 0..count {
+            pos = skip_name(msg, pos).unwrap();
+            out.push(u32::from_be_bytes([
+                msg[pos + 4],
+                msg[pos + 5],
+                msg[pos + 6],
+                msg[pos + 7],
+            ]));
+            let rdlen = u16::from_be_bytes([msg[pos + 8], msg[pos + 9]]) as usize;
+            pos += 10 + rdlen;
+        }
+        out
+    }
+
+    #[test]
+    fn parses_question() {
+        let q = query(7, "Example.COM", QTYPE_AAAA);
+        assert_eq!(
+            parse_question(&q),
+            Some(("example.com".to_string(), QTYPE_AAAA, 1, q.len()))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_questions() {
+        assert_eq!(parse_question(&[0; 11]), None);
+        // No questions
+        let mut q = query(7, "example.com", QTYPE_A);
+        q[5] = 0;
+        assert_eq!(parse_question(&q), None);
+        // Truncated before the type
+        let q = query(7, "example.com", QTYPE_A);
+        assert_eq!(parse_question(&q[..q.len() - 3]), None);
+        // Compression pointer in the question
+        let mut q = query(7, "example.com", QTYPE_A);
+        q[12] = 0xC0;
+        assert_eq!(parse_question(&q), None);
+    }
+
+    #[test]
+    fn min_ttl_across_records() {
+        let q = query(7, "example.com", QTYPE_A);
+        let a = answer(&q, &[300, 60, 900]);
+        assert_eq!(min_ttl(&a, q.len()), Some(60));
+        assert_eq!(min_ttl(&answer(&q, &[]), q.len()), None);
+    }
+
+    #[test]
+    fn cache_hit_has_remaining_ttl() {
+        let q = query(7, "example.com", QTYPE_A);
+        let key = ("example.com".to_string(), QTYPE_A, 1);
+        let mut cache = DnsCache::default();
+        cache.insert(key.clone(), &answer(&q, &[300, 60]), q.len());
+
+        cache.entries.get_mut(&key).unwrap().stored -= Duration::from_secs(10);
+        let hit = cache.get(&key, [0xAB, 0xCD]).unwrap();
+        assert_eq!(&hit[..2], &[0xAB, 0xCD]);
+        assert_eq!(ttls(&hit, q.len()), vec![290, 50]);
+    }
+
+    #[test]
+    fn failures_are_not_cached() {
+        let q = query(7, "example.com", QTYPE_A);
+        let key = ("example.com".to_string(), QTYPE_A, 1);
+        let mut cache = DnsCache::default();
+        cache.insert(
+            key.clone(),
+            &empty_response(&q, q.len(), RCODE_SERVFAIL),
+            q.len(),
+        );
+        assert!(cache.get(&key, [0, 0]).is_none());
+    }
+
+    fn tls_acceptor() -> native_tls::TlsAcceptor {
+        let cert = include_bytes!("../tests/fixtures/localhost.pem");
+        let key = include_bytes!("../tests/fixtures/localhost.key");
+        let identity = native_tls::Identity::from_pkcs8(cert, key).unwrap();
+        native_tls::TlsAcceptor::new(identity).unwrap()
+    }
+
+    /// DoT upstream stand-in on 127.0.0.1 for `connections` queries, one per
+    /// connection. Names starting with "fail" get no answer. Counts queries.
+    fn fake_dot_upstream(connections: usize) -> (u16, Arc<AtomicUsize>) {
+        let acceptor = tls_acceptor();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (tcp, _) = listener.accept().unwrap();
+                let mut tls = acceptor.accept(tcp).unwrap();
+                let mut buf = vec![0u8; MAX_MESSAGE];
+                let len = read_frame(&mut tls, &mut buf).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let (name, ..) = parse_question(&buf[..len]).unwrap();
+                if !name.starts_with("fail") {
+                    write_frame(&mut tls, &answer(&buf[..len], &[120])).unwrap();
+                    tls.flush().unwrap();
+                }
+            }
+        });
+        (port, queries)
+    }
+
+    /// DoH upstream stand-in on 127.0.0.1 that answers one POST.
+    fn fake_doh_upstream() -> u16 {
+        let acceptor = tls_acceptor();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(acceptor.accept(tcp).unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                let line = line.to_ascii_lowercase();
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut query = vec![0u8; length];
+            reader.read_exact(&mut query).unwrap();
+
+            let body = answer(&query, &[300]);
+            let mut tls = reader.into_inner();
+            write!(
+                tls,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/dns-message\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            tls.write_all(&body).unwrap();
+            tls.flush().unwrap();
+        });
+        port
+    }
+
+    fn dot(port: u16) -> DnsUpstream {
+        DnsUpstream::Dot {
+            host: "localhost".to_string(),
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+        }
+    }
+
+    #[test]
+    fn fails_over_to_the_next_upstream() {
+        // Nothing listens on a port we just released
+        let dead_port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (live_port, _) = fake_dot_upstream(1);
+        let upstreams =
+            Upstreams::with_roots(vec![dot(dead_port), dot(live_port)], &[TEST_CA]).unwrap();
+
+        let q = query(0x1234, "example.com", QTYPE_A);
+        let reply = upstreams.resolve(&q).unwrap();
+        assert_eq!(&reply[..2], &[0x12, 0x34]);
+        assert_eq!(ttls(&reply, q.len()), vec![120]);
+    }
+
+    #[test]
+    fn resolves_over_doh() {
+        let port = fake_doh_upstream();
+        let doh = DnsUpstream::Doh {
+            url: format!("https://localhost:{port}/dns-query"),
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        let upstreams = Upstreams::with_roots(vec![doh], &[TEST_CA]).unwrap();
+
+        let q = query(0x4321, "example.com", QTYPE_A);
+        let reply = upstreams.resolve(&q).unwrap();
+        assert_eq!(&reply[..2], &[0x43, 0x21]);
+        assert_eq!(ttls(&reply, q.len()), vec![300]);
+    }
+
+    #[test]
+    fn resolver_answers_caches_blocks_and_fails_over_udp() {
+        // One upstream connection per query that reaches it
+        let (port, upstream_queries) = fake_dot_upstream(2);
+        let upstreams = Upstreams::with_roots(vec![dot(port)], &[TEST_CA]).unwrap();
+        let filter = Arc::new(DnsFilter::blocking(&["ads.example"], BlockMode::Nxdomain));
+        let resolver =
+            DnsResolver::start_with("127.0.0.1:0".parse().unwrap(), upstreams, filter).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(UPSTREAM_TIMEOUT * 2)).unwrap();
+        let ask = |id: u16, name: &str| {
+            let q = query(id, name, QTYPE_A);
+            client.send_to(&q, resolver.listen_addr()).unwrap();
+            let mut buf = [0u8; MAX_MESSAGE];
+            let len = client.recv(&mut buf).unwrap();
+            assert_eq!(u16::from_be_bytes([buf[0], buf[1]]), id);
+            (buf[..len].to_vec(), q.len())
+        };
+
+        let (first, end) = ask(1, "example.com");
+        assert_eq!(rcode(&first), 0);
+        assert_eq!(ttls(&first, end), vec![120]);
+        let (cached, _) = ask(2, "example.com");
+        assert_eq!(rcode(&cached), 0);
+        assert_eq!(upstream_queries.load(Ordering::SeqCst), 1);
+
+        let (blocked, _) = ask(3, "tracker.ads.example");
+        assert_eq!(rcode(&blocked), RCODE_NXDOMAIN);
+
+        let (failed, _) = ask(4, "fail.example.com");
+        assert_eq!(rcode(&failed), RCODE_SERVFAIL);
+        assert_eq!(upstream_queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn stop_frees_the_address_at_once() {
+        let upstreams = Upstreams::with_roots(Vec::new(), &[]).unwrap();
+        let filter = Arc::new(DnsFilter::blocking(&[], BlockMode::Nxdomain));
+        let resolver =
+            DnsResolver::start_with("127.0.0.1:0".parse().unwrap(), upstreams, filter).unwrap();
+        let listen = resolver.listen_addr();
+
+        let started = Instant::now();
+        resolver.stop();
+        assert!(started.elapsed() < POLL_INTERVAL);
+        UdpSocket::bind(listen).unwrap();
+    }
+}
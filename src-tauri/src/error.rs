@@ -0,0 +1,207 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Errors as the frontend, the log and the tray see them.
+//
+// Every command fails with a `NeraError`, serialized as
+// `{ code, message, details }`: `code` is stable and meant for branching,
+// `message` is for people, `details` carries the underlying error text.
+// Internal helpers that still fail with a plain `String` convert to
+// `Internal`, keeping their text as the message.
+
+use std::{fmt, io};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{api_client::ApiError, append_log, control::RemoteError, provisioning::NotProvisioned};
+
+// ERROR_ELEVATION_REQUIRED from CreateProcess
+const ERROR_ELEVATION_REQUIRED: i32 = 740;
+
+#[derive(Clone, Debug)]
+pub enum NeraError {
+    WireGuardMissing,
+    ElevationRequired(String),
+    FirewallApplyFailed(String),
+    TunnelFailed(String),
+    HelperUnavailable(String),
+    NotProvisioned(NotProvisioned),
+    ApiUnreachable(String),
+    ApiUnauthorized,
+    ApiRejected(String),
+    ApiServerError(String),
+    PinMismatch,
+    InvalidInput(String),
+    NotFound(String),
+    Busy(String),
+    Internal(String),
+}
+
+impl NeraError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            NeraError::WireGuardMissing => "wireguard_missing",
+            NeraError::ElevationRequired(_) => "elevation_required",
+            NeraError::FirewallApplyFailed(_) => "firewall_apply_failed",
+            NeraError::TunnelFailed(_) => "tunnel_failed",
+            NeraError::HelperUnavailable(_) => "helper_unavailable",
+            NeraError::NotProvisioned(_) => "not_provisioned",
+            NeraError::ApiUnreachable(_) => "api_unreachable",
+            NeraError::ApiUnauthorized => "api_unauthorized",
+            NeraError::ApiRejected(_) => "api_rejected",
+            NeraError::ApiServerError(_) => "api_server_error",
+            NeraError::PinMismatch => "pin_mismatch",
+            NeraError::InvalidInput(_) => "invalid_input",
+            NeraError::NotFound(_) => "not_found",
+            NeraError::Busy(_) => "busy",
+            NeraError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            NeraError::WireGuardMissing => {
+                "WireGuard is not installed. Install it from wireguard.com and try again."
+                    .to_string()
+            }
+            NeraError::ElevationRequired(_) => {
+                "Nera VPN needs administrator rights for this. Restart it as administrator."
+                    .to_string()
+            }
+            NeraError::FirewallApplyFailed(_) => {
+                "The firewall rules for the kill switch could not be applied.".to_string()
+            }
+            NeraError::TunnelFailed(_) => "The VPN tunnel could not be started.".to_string(),
+            NeraError::HelperUnavailable(_) => {
+                "The Nera VPN helper service isn't running. Start it, or reinstall it from Settings."
+                    .to_string()
+            }
+            NeraError::NotProvisioned(e) => e.to_string(),
+            NeraError::ApiUnreachable(_) => "Could not reach the Nera server.".to_string(),
+            NeraError::ApiUnauthorized => {
+                "Your session has expired. Please sign in again.".to_string()
+            }
+            NeraError::ApiServerError(_) => {
+                "The Nera server ran into a problem. Try again shortly.".to_string()
+            }
+            NeraError::PinMismatch => ApiError::PinMismatch.to_string(),
+            NeraError::ApiRejected(message)
+            | NeraError::InvalidInput(message)
+            | NeraError::NotFound(message)
+            | NeraError::Busy(message)
+            | NeraError::Internal(message) => message.clone(),
+        }
+    }
+
+    pub fn details(&self) -> Option<String> {
+        match self {
+            NeraError::ElevationRequired(d)
+            | NeraError::FirewallApplyFailed(d)
+            | NeraError::TunnelFailed(d)
+            | NeraError::HelperUnavailable(d)
+            | NeraError::ApiUnreachable(d)
+            | NeraError::ApiServerError(d) => Some(d.clone()),
+            _ => None,
+        }
+    }
+
+    /// Short form for the tray tooltip.
+    pub fn tray_text(&self) -> &'static str {
+        match self {
+            NeraError::WireGuardMissing => "WireGuard not installed",
+            NeraError::ElevationRequired(_) => "Administrator rights needed",
+            NeraError::FirewallApplyFailed(_) => "Kill switch failed",
+            NeraError::HelperUnavailable(_) => "Helper service not running",
+            NeraError::NotProvisioned(_) | NeraError::ApiUnauthorized => "Sign-in needed",
+            NeraError::ApiUnreachable(_) | NeraError::ApiServerError(_) => "Server unreachable",
+            NeraError::PinMismatch => "Server identity mismatch",
+            _ => "Connection failed",
+        }
+    }
+
+    /// Writes `context: [code] message (details)` to the log.
+    pub fn log(&self, context: &str) {
+        append_log(&format!("{context}: [{}] {self}", self.code())).ok();
+    }
+
+    /// Maps a failure to start `program`.
+    pub fn spawn_failed(program: &str, e: io::Error) -> Self {
+        if e.raw_os_error() == Some(ERROR_ELEVATION_REQUIRED)
+            || e.kind() == io::ErrorKind::PermissionDenied
+        {
+            NeraError::ElevationRequired(format!("{program}: {e}"))
+        } else {
+            NeraError::Internal(format!("Failed to run {program}: {e}"))
+        }
+    }
+}
+
+impl fmt::Display for NeraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.details() {
+            Some(details) => write!(f, "{} ({details})", self.message()),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl Serialize for NeraError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("NeraError", 3)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.message())?;
+        s.serialize_field("details", &self.details())?;
+        s.end()
+    }
+}
+
+impl From<String> for NeraError {
+    fn from(message: String) -> Self {
+        NeraError::Internal(message)
+    }
+}
+
+impl From<NotProvisioned> for NeraError {
+    fn from(e: NotProvisioned) -> Self {
+        NeraError::NotProvisioned(e)
+    }
+}
+
+impl From<ApiError> for NeraError {
+    fn from(e: ApiError) -> Self {
+        match e {
+            ApiError::Network(_) | ApiError::Timeout => NeraError::ApiUnreachable(e.to_string()),
+            // A bare 401 (wrong password at login) is a rejection; an expired
+            // session has already been through a refresh attempt
+            ApiError::SessionExpired => NeraError::ApiUnauthorized,
+            ApiError::Client { .. } => NeraError::ApiRejected(e.to_string()),
+            ApiError::Server { .. } | ApiError::Decode(_) => {
+                NeraError::ApiServerError(e.to_string())
+            }
+            ApiError::PinMismatch => NeraError::PinMismatch,
+        }
+    }
+}
+
+/// Rebuilds an error the privileged helper reported. Only the variants its
+/// operations can fail with are mapped back; anything else stays `Internal`.
+impl From<RemoteError> for NeraError {
+    fn from(e: RemoteError) -> Self {
+        let details = e.details.clone().unwrap_or_else(|| e.message.clone());
+        match e.code.as_str() {
+            "wireguard_missing" => NeraError::WireGuardMissing,
+            "elevation_required" => NeraError::ElevationRequired(details),
+            "firewall_apply_failed" => NeraError::FirewallApplyFailed(details),
+            "tunnel_failed" => NeraError::TunnelFailed(details),
+            "invalid_input" => NeraError::InvalidInput(e.message),
+            _ => NeraError::Internal(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Sequenced backend events.
+//
+// Every event goes out as `{ seq, data }`, with `seq` increasing by one per
+// event across all event names. State changes are also kept in a bounded
+// replay buffer; high-rate readings (traffic, latency) are numbered but not
+// kept, since the next one supersedes them.
+//
+// A frontend that mounts late or reloads listens first, then calls
+// `get_state_snapshot` and ignores any event with `seq <= snapshot.seq`.
+// One that was only briefly away can call `get_events_since` instead.
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{
+    error::NeraError,
+    load_settings,
+    provisioning::{self, ProvisioningState},
+    VpnState,
+};
+
+const REPLAY_CAPACITY: usize = 256;
+
+#[derive(Clone, Serialize)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: String,
+    pub data: serde_json::Value,
+}
+
+#[derive(Clone, Serialize)]
+struct Envelope<'a> {
+    seq: u64,
+    data: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct EventsSince {
+    pub events: Vec<SequencedEvent>,
+    /// False if some events after the requested `seq` were already dropped;
+    /// take a fresh snapshot instead.
+    pub complete: bool,
+}
+
+#[derive(Serialize)]
+pub struct StateSnapshot {
+    /// Events up to and including this one are reflected below.
+    pub seq: u64,
+    pub vpn_connected: bool,
+    pub kill_switch_enabled: bool,
+    pub recovery_mode: bool,
+    pub tunnel_interface: Option<String>,
+    pub selected_server: String,
+    pub provisioning: ProvisioningState,
+    pub active_identity_id: Option<String>,
+    /// Last failure of a background action (tray, network rules), if it
+    /// hasn't been resolved since.
+    pub error: Option<NeraError>,
+}
+
+#[derive(Default)]
+struct LogInner {
+    last_seq: u64,
+    /// Highest `seq` no longer in `buffer`.
+    evicted_through: u64,
+    buffer: VecDeque<SequencedEvent>,
+    error: Option<NeraError>,
+}
+
+#[derive(Default)]
+pub struct EventLog {
+    inner: Mutex<LogInner>,
+}
+
+impl EventLog {
+    fn since(&self, seq: u64) -> EventsSince {
+        let inner = self.inner.lock().unwrap();
+        EventsSince {
+            events: inner
+                .buffer
+                .iter()
+                .filter(|e| e.seq > seq)
+                .cloned()
+                .collect(),
+            complete: seq >= inner.evicted_through,
+        }
+    }
+}
+
+impl LogInner {
+    /// Numbers an event and, if `keep`, buffers it for replay.
+    fn record(&mut self, event: &str, data: &serde_json::Value, keep: bool) -> u64 {
+        self.last_seq += 1;
+        let seq = self.last_seq;
+
+        if keep {
+            if self.buffer.len() == REPLAY_CAPACITY {
+                if let Some(dropped) = self.buffer.pop_front() {
+                    self.evicted_through = dropped.seq;
+                }
+            }
+            self.buffer.push_back(SequencedEvent {
+                seq,
+                event: event.to_string(),
+                data: data.clone(),
+            });
+        }
+        seq
+    }
+}
+
+fn send(app: &AppHandle, event: &str, payload: impl Serialize, keep: bool) -> tauri::Result<()> {
+    let data = serde_json::to_value(payload)?;
+    let state = app.state::<VpnState>();
+    // Held while emitting so events leave in `seq` order
+    let mut inner = state.events.inner.lock().unwrap();
+    let seq = inner.record(event, &data, keep);
+
+    app.emit(event, Envelope { seq, data: &data })
+}
+
+/// Emits a state change and keeps it for replay.
+pub fn emit(app: &AppHandle, event: &str, payload: impl Serialize) -> tauri::Result<()> {
+    send(app, event, payload, true)
+}
+
+/// Emits a reading that the next one replaces; it isn't kept for replay.
+pub fn emit_transient(app: &AppHandle, event: &str, payload: impl Serialize) -> tauri::Result<()> {
+    send(app, event, payload, false)
+}
+
+/// Records a background failure for the snapshot and emits `nera-error`.
+pub fn report_error(app: &AppHandle, error: &NeraError) {
+    app.state::<VpnState>().events.inner.lock().unwrap().error = Some(error.clone());
+    emit(app, "nera-error", error).ok();
+}
+
+pub fn clear_error(app: &AppHandle) {
+    let state = app.state::<VpnState>();
+    let had_error = state.events.inner.lock().unwrap().error.take().is_some();
+    if had_error {
+        emit(app, "nera-error-cleared", ()).ok();
+    }
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_state_snapshot(state: State<'_, VpnState>) -> StateSnapshot {
+    // Read `seq` before the state: a change that lands in between is then
+    // both in the snapshot and re-sent as a later event, never lost.
+    let (seq, error) = {
+        let inner = state.events.inner.lock().unwrap();
+        (inner.last_seq, inner.error.clone())
+    };
+    let vpn_connected = *state.connected.lock().unwrap();
+    let kill_switch_enabled = *state.kill_switch_enabled.lock().unwrap();
+    let settings = load_settings();
+
+    StateSnapshot {
+        seq,
+        vpn_connected,
+        kill_switch_enabled,
+        recovery_mode: kill_switch_enabled && !vpn_connected,
+        tunnel_interface: state.tunnel_interface.lock().unwrap().clone(),
+        selected_server: settings.selected_server.clone(),
+        provisioning: provisioning::current(&settings),
+        active_identity_id: settings.active_identity_id.clone(),
+        error,
+    }
+}
+
+/// Buffered state events after `seq`, oldest first.
+#[tauri::command]
+pub fn get_events_since(state: State<'_, VpnState>, seq: u64) -> EventsSince {
+    state.events.since(seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(log: &EventLog, event: &str, keep: bool) -> u64 {
+        let data = serde_json::json!({ "event": event });
+        log.inner.lock().unwrap().record(event, &data, keep)
+    }
+
+    fn seqs(since: &EventsSince) -> Vec<u64> {
+        since.events.iter().map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn replays_everything_after_seq_until_full() {
+        let log = EventLog::default();
+        for _ in 0..REPLAY_CAPACITY {
+            record(&log, "vpn-status", true);
+        }
+
+        let all = log.since(0);
+        assert!(all.complete);
+        assert_eq!(all.events.len(), REPLAY_CAPACITY);
+        assert_eq!(seqs(&log.since(250)), [251, 252, 253, 254, 255, 256]);
+    }
+
+    #[test]
+    fn eviction_drops_the_oldest_and_marks_older_reads_incomplete() {
+        let log = EventLog::default();
+        for _ in 0..REPLAY_CAPACITY + 2 {
+            record(&log, "vpn-status", true);
+        }
+
+        let since = log.since(0);
+        assert!(!since.complete);
+        assert_eq!(since.events.len(), REPLAY_CAPACITY);
+        assert_eq!(since.events[0].seq, 3);
+        // Up to the last evicted event something is missing; after it nothing is
+        assert!(!log.since(1).complete);
+        assert!(log.since(2).complete);
+        assert_eq!(log.since(2).events.len(), REPLAY_CAPACITY);
+    }
+
+    #[test]
+    fn transient_events_are_numbered_but_not_kept() {
+        let log = EventLog::default();
+        assert_eq!(record(&log, "vpn-status", true), 1);
+        assert_eq!(record(&log, "traffic-stats", false), 2);
+        assert_eq!(record(&log, "latency-stats", false), 3);
+        assert_eq!(record(&log, "kill-switch-changed", true), 4);
+
+        let since = log.since(0);
+        assert!(since.complete);
+        assert_eq!(seqs(&since), [1, 4]);
+        assert_eq!(since.events[1].event, "kill-switch-changed");
+        assert!(log.since(4).events.is_empty());
+
+        // Transient events never fill the buffer or evict anything
+        for _ in 0..REPLAY_CAPACITY * 2 {
+            record(&log, "traffic-stats", false);
+        }
+        assert!(log.since(0).complete);
+        assert_eq!(seqs(&log.since(0)), [1, 4]);
+    }
+}
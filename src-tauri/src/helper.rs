@@ -0,0 +1,1394 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Privileged helper (`nera-helper`).
+//
+// Changing the firewall policy and installing tunnel services needs
+// administrator rights. Rather than running the whole app elevated, those
+// operations live in a small helper installed as a service (LocalSystem on
+// Windows, root elsewhere). The app stays unelevated and asks the helper over
+// loopback TCP.
+//
+// Pairing: `install_helper` generates a random key, hands it to the elevated
+// `nera-helper install` without writing it to disk (on stdin, or through a
+// named pipe only the user and administrators can open on Windows) and keeps
+// it sealed in settings. The installer registers a copy of itself in its own
+// restricted directory, so the service doesn't run a binary the user can swap.
+// Every connection starts with a mutual HMAC-SHA256 challenge-response over
+// that key, so neither side talks to an impostor.
+// After that it's JSON-RPC 2.0 lines, as on the control socket, limited to:
+//   ping                                       -> "pong"
+//   enable_kill_switch { scope }               -> null
+//   disable_kill_switch                        -> null
+//   install_tunnel { name, config }            -> null
+//   uninstall_tunnel { name }                  -> false if it wasn't installed
+//   wg_show { interface, field }               -> the output, or null
+//   set_private_key { interface, key }         -> null
+//   set_preshared_key { interface, peer, psk } -> null
+//   probe_path_mtu { host }                    -> the path MTU, or null
+// Tunnel names, config keys and values that route traffic (peer, endpoint,
+// DNS), MTU probe targets and kill switch scopes are checked against what the app itself
+// produces; in particular a config can't carry scripts.
+//
+// The helper remembers the kill switch scope and applies it again whenever it
+// starts, so the block holds across reboots whether or not the app runs.
+// Until a helper is paired the app does all of this itself, as before.
+
+use std::{
+    ffi::OsStr,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::{
+    append_log,
+    control::{self, RpcError},
+    disable_kill_switch_local, dns, enable_kill_switch_local,
+    error::NeraError,
+    install_tunnel_local, load_settings, mtu, secrets, servers, traffic, uninstall_tunnel_local,
+    update_settings, KillSwitchScope,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PORT: u16 = 47830;
+#[cfg_attr(not(windows), allow(dead_code))]
+const SERVICE_NAME: &str = "NeraVpnHelper";
+
+// Tunnel names (config basenames) the app uses
+const TUNNEL_NAMES: &[&str] = &["nera", "nera-temp", "nera-hop"];
+const WG_SHOW_FIELDS: &[&str] = &["transfer", "latest-handshakes", "peers", "endpoints"];
+// Everything the app's config templates and rewrites produce, nothing more
+const INTERFACE_KEYS: &[&str] = &["PrivateKey", "Address", "DNS", "MTU", "ListenPort"];
+const PEER_KEYS: &[&str] = &[
+    "PublicKey",
+    "PresharedKey",
+    "AllowedIPs",
+    "Endpoint",
+    "PersistentKeepalive",
+];
+
+// Mixed into the MACs so one side's answer can't be replayed as the other's
+const CLIENT_ROLE: &[u8] = b"nera-client";
+const HELPER_ROLE: &[u8] = b"nera-helper";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+// Installing a tunnel service can take a while
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_LINE: u64 = 64 * 1024;
+
+const USAGE: &str = "\
+Usage: nera-helper <command>
+
+Commands:
+  install --pair <src>   Take the pairing key from <src> (- for stdin, or a pipe)
+                         and install the helper service
+  uninstall              Remove the service, lift the kill switch and remove tunnels
+  serve                  Run the helper in the foreground";
+
+/// What the helper puts back when it starts.
+#[derive(Default, Serialize, Deserialize)]
+struct HelperState {
+    #[serde(default)]
+    kill_switch: Option<KillSwitchScope>,
+}
+
+#[derive(Serialize)]
+pub struct HelperStatus {
+    pub paired: bool,
+    /// The paired helper answered just now.
+    pub running: bool,
+}
+
+#[derive(Deserialize)]
+struct ScopeParams {
+    scope: KillSwitchScope,
+}
+
+#[derive(Deserialize)]
+struct TunnelParams {
+    name: String,
+    config: String,
+}
+
+#[derive(Deserialize)]
+struct NameParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ShowParams {
+    interface: String,
+    field: String,
+}
+
+#[derive(Deserialize)]
+struct ProbeParams {
+    host: IpAddr,
+}
+
+#[derive(Deserialize)]
+struct PrivateKeyParams {
+    interface: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct PresharedKeyParams {
+    interface: String,
+    peer: String,
+    psk: String,
+}
+
+// --- Files ---
+
+#[cfg(windows)]
+fn helper_dir_path() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("Nera VPN")
+}
+
+#[cfg(not(windows))]
+fn helper_dir_path() -> PathBuf {
+    PathBuf::from("/var/lib/nera-helper")
+}
+
+/// The helper's own directory: configs, key, state and log.
+fn helper_dir() -> Result<PathBuf, String> {
+    let dir = helper_dir_path();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    Ok(dir)
+}
+
+fn helper_file(name: &str) -> Result<PathBuf, String> {
+    helper_dir().map(|dir| dir.join(name))
+}
+
+/// Limits a directory (the helper's own, or one holding a key file) to SYSTEM
+/// and administrators.
+#[cfg(windows)]
+pub fn restrict_dir(dir: &Path) -> Result<(), String> {
+    let output = crate::hidden_command("icacls")
+        .arg(dir)
+        .args([
+            "/inheritance:r",
+            "/grant:r",
+            "*S-1-5-18:(OI)(CI)F",
+            "/grant:r",
+            "*S-1-5-32-544:(OI)(CI)F",
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run icacls: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to restrict {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn restrict_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("Failed to restrict {}: {e}", dir.display()))
+}
+
+fn write_owner_only(path: &Path, content: &str) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+/// The helper logs next to its state; the app's log lives in a user profile.
+fn log(line: &str) {
+    let file = helper_file("helper.log")
+        .ok()
+        .and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+    if let Some(mut file) = file {
+        let ts = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        let _ = writeln!(file, "[{ts}] {line}");
+    }
+}
+
+fn load_state() -> HelperState {
+    helper_file("state.json")
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(state: &HelperState) {
+    let saved = helper_file("state.json").and_then(|path| {
+        let content = serde_json::to_string_pretty(state).map_err(|e| e.to_string())?;
+        fs::write(path, content).map_err(|e| e.to_string())
+    });
+    if let Err(e) = saved {
+        log(&format!("Failed to save state: {e}"));
+    }
+}
+
+// --- Key & Handshake ---
+
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode_32(text: &str) -> Option<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(text.trim())
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+}
+
+fn decode_key(text: &str) -> Result<Vec<u8>, String> {
+    decode_32(text).ok_or_else(|| "Not a valid helper key.".to_string())
+}
+
+/// The pairing key, read per connection so re-pairing takes effect at once.
+fn read_key() -> Result<Vec<u8>, String> {
+    let path = helper_file("helper.key")?;
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("Not paired ({}): {e}", path.display()))?;
+    decode_key(&text)
+}
+
+fn mac(key: &[u8], role: &[u8], challenge: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(role);
+    mac.update(challenge);
+    mac
+}
+
+fn sign(key: &[u8], role: &[u8], challenge: &[u8]) -> String {
+    general_purpose::STANDARD.encode(mac(key, role, challenge).finalize().into_bytes())
+}
+
+/// Checks `response` against our own MAC of `challenge`, in constant time.
+fn verify(key: &[u8], role: &[u8], challenge: &[u8], response: &Value) -> bool {
+    match response
+        .as_str()
+        .and_then(|r| general_purpose::STANDARD.decode(r).ok())
+    {
+        Some(tag) => mac(key, role, challenge).verify_slice(&tag).is_ok(),
+        None => false,
+    }
+}
+
+fn challenge_in(message: &Value) -> Result<Vec<u8>, String> {
+    message["challenge"]
+        .as_str()
+        .and_then(decode_32)
+        .ok_or_else(|| "missing challenge".to_string())
+}
+
+fn send(mut stream: &TcpStream, message: &Value) -> Result<(), String> {
+    writeln!(stream, "{message}").map_err(|e| format!("write failed: {e}"))
+}
+
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String, String> {
+    let mut line = String::new();
+    match reader.by_ref().take(MAX_LINE).read_line(&mut line) {
+        Ok(0) => Err("connection closed".to_string()),
+        Ok(_) => Ok(line),
+        Err(e) => Err(format!("read failed: {e}")),
+    }
+}
+
+fn receive(reader: &mut BufReader<TcpStream>) -> Result<Value, String> {
+    serde_json::from_str(&read_line(reader)?).map_err(|e| format!("bad message: {e}"))
+}
+
+/// Helper side: the client proves it holds the key before it learns anything.
+fn authenticate_client(key: &[u8], reader: &mut BufReader<TcpStream>) -> Result<(), String> {
+    let ours = random_bytes();
+    send(
+        reader.get_ref(),
+        &json!({ "challenge": general_purpose::STANDARD.encode(ours) }),
+    )?;
+
+    let hello = receive(reader)?;
+    if !verify(key, CLIENT_ROLE, &ours, &hello["response"]) {
+        return Err("client failed authentication".to_string());
+    }
+    let theirs = challenge_in(&hello)?;
+    send(
+        reader.get_ref(),
+        &json!({ "response": sign(key, HELPER_ROLE, &theirs) }),
+    )
+}
+
+/// App side: answers the helper's challenge and checks its answer to ours.
+fn authenticate_helper(key: &[u8], reader: &mut BufReader<TcpStream>) -> Result<(), String> {
+    let theirs = challenge_in(&receive(reader)?)?;
+    let ours = random_bytes();
+    send(
+        reader.get_ref(),
+        &json!({
+            "response": sign(key, CLIENT_ROLE, &theirs),
+            "challenge": general_purpose::STANDARD.encode(ours),
+        }),
+    )?;
+
+    let reply = receive(reader)?;
+    if !verify(key, HELPER_ROLE, &ours, &reply["response"]) {
+        return Err("helper failed authentication".to_string());
+    }
+    Ok(())
+}
+
+// --- Client ---
+
+fn connect(key: &[u8]) -> Result<BufReader<TcpStream>, String> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, PORT));
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .map_err(|e| format!("Can't reach the helper: {e}"))?;
+    stream
+        .set_read_timeout(Some(AUTH_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream);
+    authenticate_helper(key, &mut reader)?;
+    Ok(reader)
+}
+
+fn request(key: &[u8], method: &str, params: Value) -> Result<Value, NeraError> {
+    let mut reader = connect(key).map_err(NeraError::HelperUnavailable)?;
+    let lost = |e: String| NeraError::HelperUnavailable(format!("Lost the helper: {e}"));
+
+    reader
+        .get_ref()
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(|e| lost(e.to_string()))?;
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    send(reader.get_ref(), &request).map_err(lost)?;
+    let line = read_line(&mut reader).map_err(lost)?;
+    control::parse_response(&line).map_err(NeraError::from)
+}
+
+/// Runs `method` on the helper. `None` if no helper is paired, in which case
+/// the caller does the work itself; a paired helper that can't be reached is
+/// `HelperUnavailable`.
+pub fn call(method: &str, params: Value) -> Option<Result<Value, NeraError>> {
+    let sealed = load_settings().helper_key?;
+    Some(
+        secrets::open(&sealed)
+            .and_then(|key| decode_key(&key))
+            .map_err(NeraError::from)
+            .and_then(|key| request(&key, method, params)),
+    )
+}
+
+fn status() -> HelperStatus {
+    match call("ping", Value::Null) {
+        Some(reply) => HelperStatus {
+            paired: true,
+            running: reply.is_ok(),
+        },
+        None => HelperStatus {
+            paired: false,
+            running: false,
+        },
+    }
+}
+
+// --- Server ---
+
+fn refused(what: String) -> NeraError {
+    NeraError::InvalidInput(format!("Refused by the helper: {what}"))
+}
+
+fn check_tunnel_name(name: &str) -> Result<(), NeraError> {
+    if TUNNEL_NAMES.contains(&name) {
+        Ok(())
+    } else {
+        Err(refused(format!("unknown tunnel {name}")))
+    }
+}
+
+fn check_peer(public_key: &str) -> Result<(), NeraError> {
+    if servers::SERVERS.iter().any(|s| s.public_key == public_key) {
+        Ok(())
+    } else {
+        Err(refused(format!("unknown peer {public_key}")))
+    }
+}
+
+fn check_key(key: &str) -> Result<(), NeraError> {
+    decode_32(key)
+        .map(|_| ())
+        .ok_or_else(|| refused("malformed key".to_string()))
+}
+
+/// A catalog server on one of its ports, or the local end of our own relay.
+fn known_endpoint(addr: &SocketAddr) -> bool {
+    addr.ip().is_loopback()
+        || servers::SERVERS
+            .iter()
+            .any(|s| s.ports.iter().any(|p| s.socket_addr_on(*p) == Some(*addr)))
+}
+
+/// Only catalog servers, their relays, and the local end of our own relay.
+fn check_scope(scope: &KillSwitchScope) -> Result<(), NeraError> {
+    for name in &scope.interfaces {
+        check_tunnel_name(name)?;
+    }
+    for addr in &scope.endpoints {
+        if !known_endpoint(addr) {
+            return Err(refused(format!("endpoint {addr}")));
+        }
+    }
+    for addr in &scope.relays {
+        if !servers::SERVERS
+            .iter()
+            .any(|s| s.relay_addr() == Some(*addr))
+        {
+            return Err(refused(format!("relay {addr}")));
+        }
+    }
+    Ok(())
+}
+
+fn check_probe_host(host: IpAddr) -> Result<(), NeraError> {
+    if servers::SERVERS
+        .iter()
+        .any(|s| s.socket_addr().map(|a| a.ip()) == Some(host))
+    {
+        Ok(())
+    } else {
+        Err(refused(format!("MTU probe to {host}")))
+    }
+}
+
+fn check_endpoint(value: &str) -> Result<(), NeraError> {
+    match value.parse::<SocketAddr>() {
+        Ok(addr) if known_endpoint(&addr) => Ok(()),
+        _ => Err(refused(format!("endpoint {value}"))),
+    }
+}
+
+/// Our local resolver or the templates' plain one.
+fn check_dns(value: &str) -> Result<(), NeraError> {
+    for server in value.split(',').map(str::trim) {
+        let known = server.parse::<IpAddr>().map_or(false, |ip| {
+            ip == dns::listen_addr().ip() || ip == dns::PLAIN_DNS
+        });
+        if !known {
+            return Err(refused(format!("DNS server {server}")));
+        }
+    }
+    Ok(())
+}
+
+/// Accepts only the sections and keys the app's own configs use, with a
+/// catalog server as the peer and endpoint and our own DNS. Other values are
+/// never echoed: they hold keys.
+fn check_config(config: &str) -> Result<(), NeraError> {
+    let mut allowed: &[&str] = &[];
+    for line in config.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            allowed = if line.eq_ignore_ascii_case("[Interface]") {
+                INTERFACE_KEYS
+            } else if line.eq_ignore_ascii_case("[Peer]") {
+                PEER_KEYS
+            } else {
+                return Err(refused(format!("config section {line}")));
+            };
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| refused("malformed config line".to_string()))?;
+        let key = key.trim();
+        if !allowed.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            return Err(refused(format!("config key {key}")));
+        }
+        let value = value.trim();
+        if key.eq_ignore_ascii_case("PublicKey") {
+            check_peer(value)?;
+        } else if key.eq_ignore_ascii_case("Endpoint") {
+            check_endpoint(value)?;
+        } else if key.eq_ignore_ascii_case("DNS") {
+            check_dns(value)?;
+        }
+    }
+    Ok(())
+}
+
+fn dispatch(method: &str, params: Value) -> Result<Value, RpcError> {
+    if method != "ping" && method != "wg_show" {
+        log(&format!("Request: {method}"));
+    }
+    match method {
+        "ping" => Ok(json!("pong")),
+        "enable_kill_switch" => {
+            let ScopeParams { scope } = control::parse_params(params)?;
+            check_scope(&scope)?;
+            enable_kill_switch_local(&scope)?;
+            save_state(&HelperState {
+                kill_switch: Some(scope),
+            });
+            Ok(Value::Null)
+        }
+        "disable_kill_switch" => {
+            disable_kill_switch_local()?;
+            save_state(&HelperState::default());
+            Ok(Value::Null)
+        }
+        "install_tunnel" => {
+            let params: TunnelParams = control::parse_params(params)?;
+            check_tunnel_name(&params.name)?;
+            check_config(&params.config)?;
+            let path = helper_file(&format!("{}.conf", params.name)).map_err(NeraError::from)?;
+            write_owner_only(&path, &params.config).map_err(NeraError::from)?;
+            install_tunnel_local(&path)?;
+            Ok(Value::Null)
+        }
+        "uninstall_tunnel" => {
+            let params: NameParams = control::parse_params(params)?;
+            check_tunnel_name(&params.name)?;
+            Ok(json!(uninstall_tunnel_local(&params.name)?))
+        }
+        "wg_show" => {
+            let params: ShowParams = control::parse_params(params)?;
+            check_tunnel_name(&params.interface)?;
+            if !WG_SHOW_FIELDS.contains(&params.field.as_str()) {
+                return Err(refused(format!("wg show {}", params.field)).into());
+            }
+            Ok(json!(traffic::run_wg_show(
+                &params.interface,
+                &params.field
+            )))
+        }
+        "probe_path_mtu" => {
+            let ProbeParams { host } = control::parse_params(params)?;
+            check_probe_host(host)?;
+            Ok(json!(mtu::probe_path_mtu(host)))
+        }
+        "set_private_key" => {
+            let params: PrivateKeyParams = control::parse_params(params)?;
+            check_tunnel_name(&params.interface)?;
+            check_key(&params.key)?;
+            let dir = helper_dir().map_err(NeraError::from)?;
+            traffic::run_wg_set_secret(&dir, &params.interface, &["private-key"], &params.key)
+                .map_err(NeraError::from)?;
+            Ok(Value::Null)
+        }
+        "set_preshared_key" => {
+            let params: PresharedKeyParams = control::parse_params(params)?;
+            check_tunnel_name(&params.interface)?;
+            check_peer(&params.peer)?;
+            check_key(&params.psk)?;
+            let dir = helper_dir().map_err(NeraError::from)?;
+            let setting = ["peer", params.peer.as_str(), "preshared-key"];
+            traffic::run_wg_set_secret(&dir, &params.interface, &setting, &params.psk)
+                .map_err(NeraError::from)?;
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::method_not_found(method)),
+    }
+}
+
+fn serve_connection(stream: TcpStream) {
+    let key = match read_key() {
+        Ok(key) => key,
+        Err(e) => {
+            log(&format!("Refusing connection: {e}"));
+            return;
+        }
+    };
+    if stream.set_read_timeout(Some(AUTH_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    if let Err(e) = authenticate_client(&key, &mut reader) {
+        log(&format!("Rejected a connection: {e}"));
+        return;
+    }
+
+    // An authenticated client may take its time between requests
+    let _ = reader.get_ref().set_read_timeout(None);
+    while let Ok(line) = read_line(&mut reader) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = control::handle_line(&line, dispatch) {
+            if send(reader.get_ref(), &response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Puts the last kill switch back, e.g. after a reboot.
+fn restore_kill_switch() {
+    if let Some(scope) = load_state().kill_switch {
+        match enable_kill_switch_local(&scope) {
+            Ok(()) => log("Kill Switch restored."),
+            Err(e) => log(&format!("Kill Switch restore failed: {e}")),
+        }
+    }
+}
+
+/// Binds the port and restores the kill switch; the caller then accepts.
+fn start() -> Result<TcpListener, String> {
+    read_key()?;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, PORT))
+        .map_err(|e| format!("Failed to listen on port {PORT}: {e}"))?;
+    restore_kill_switch();
+    log(&format!("Helper listening on 127.0.0.1:{PORT}."));
+    Ok(listener)
+}
+
+fn accept_loop(listener: TcpListener) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || serve_connection(stream));
+            }
+            Err(e) => log(&format!("Accept failed: {e}")),
+        }
+    }
+}
+
+// --- Install ---
+
+/// The pairing key from stdin (`-`) or the app's pipe.
+fn read_pairing_key(source: &str) -> Result<String, String> {
+    let mut key = String::new();
+    let read = if source == "-" {
+        io::stdin().read_to_string(&mut key)
+    } else {
+        fs::File::open(source).and_then(|mut pipe| pipe.read_to_string(&mut key))
+    };
+    read.map_err(|e| format!("Failed to read the pairing key from {source}: {e}"))?;
+    Ok(key)
+}
+
+/// Copies this binary into `dir`, where only administrators can replace it.
+fn install_exe(dir: &Path) -> Result<PathBuf, String> {
+    let current = std::env::current_exe().map_err(|e| format!("Can't locate nera-helper: {e}"))?;
+    let target = dir.join(
+        current
+            .file_name()
+            .unwrap_or_else(|| OsStr::new("nera-helper")),
+    );
+    if current == target {
+        return Ok(target);
+    }
+
+    // A running service holds the old copy open
+    platform::stop_service()?;
+    let staged = target.with_extension("new");
+    fs::copy(&current, &staged)
+        .and_then(|_| fs::rename(&staged, &target))
+        .map_err(|e| format!("Failed to copy nera-helper to {}: {e}", dir.display()))?;
+    Ok(target)
+}
+
+fn install(pair_source: &str) -> Result<(), String> {
+    let key = read_pairing_key(pair_source)?;
+    decode_key(&key)?;
+
+    let dir = helper_dir()?;
+    restrict_dir(&dir)?;
+    write_owner_only(&dir.join("helper.key"), key.trim())?;
+    log("Paired with the app.");
+
+    platform::install_service(&install_exe(&dir)?)
+}
+
+fn uninstall() -> Result<(), String> {
+    platform::uninstall_service()?;
+
+    // Nothing the helper set up may outlive it
+    if let Err(e) = disable_kill_switch_local() {
+        log(&format!("Kill Switch removal failed: {e}"));
+    }
+    for name in TUNNEL_NAMES {
+        let _ = uninstall_tunnel_local(name);
+    }
+    if let Ok(dir) = helper_dir() {
+        let _ = fs::remove_file(dir.join("helper.key"));
+        let _ = fs::remove_file(dir.join("state.json"));
+    }
+    log("Uninstalled.");
+    Ok(())
+}
+
+fn serve() -> Result<(), String> {
+    accept_loop(start()?);
+    Ok(())
+}
+
+/// Entry point of the `nera-helper` binary. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["install", "--pair", source] => install(source),
+        ["uninstall"] => uninstall(),
+        ["serve"] => serve(),
+        // Started by the service manager
+        ["service"] => platform::run_service(),
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            log(&e);
+            eprintln!("nera-helper: {e}");
+            1
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{
+        ffi::{OsStr, OsString},
+        path::Path,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use windows_service::{
+        define_windows_service,
+        service::{
+            Service, ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+            ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    use super::{accept_loop, log, start, SERVICE_NAME};
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    fn service_main(_args: Vec<OsString>) {
+        if let Err(e) = run_until_stopped() {
+            log(&format!("Service failed: {e}"));
+        }
+    }
+
+    fn run_until_stopped() -> Result<(), String> {
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let handler = move |control: ServiceControl| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                let _ = stop_tx.send(());
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status = service_control_handler::register(SERVICE_NAME, handler)
+            .map_err(|e| format!("Failed to register the service handler: {e}"))?;
+        let report = |current_state, controls_accepted, exit_code| {
+            status
+                .set_service_status(ServiceStatus {
+                    service_type: ServiceType::OWN_PROCESS,
+                    current_state,
+                    controls_accepted,
+                    exit_code,
+                    checkpoint: 0,
+                    wait_hint: Duration::default(),
+                    process_id: None,
+                })
+                .map_err(|e| format!("Failed to report service status: {e}"))
+        };
+
+        let listener = match start() {
+            Ok(listener) => listener,
+            Err(e) => {
+                let failed = ServiceExitCode::ServiceSpecific(1);
+                report(ServiceState::Stopped, ServiceControlAccept::empty(), failed)?;
+                return Err(e);
+            }
+        };
+        report(
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            ServiceExitCode::NO_ERROR,
+        )?;
+        thread::spawn(move || accept_loop(listener));
+        let _ = stop_rx.recv();
+
+        // The kill switch stays as it is: the firewall keeps enforcing it
+        report(
+            ServiceState::Stopped,
+            ServiceControlAccept::empty(),
+            ServiceExitCode::NO_ERROR,
+        )
+    }
+
+    pub fn run_service() -> Result<(), String> {
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+            .map_err(|e| format!("Not started by the service manager: {e}"))
+    }
+
+    /// Registers the helper to start with Windows, as LocalSystem, and starts it.
+    pub fn install_service(exe: &Path) -> Result<(), String> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )
+        .map_err(|e| format!("Failed to open the service manager: {e}"))?;
+
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from("Nera VPN Helper"),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: exe.to_path_buf(),
+            launch_arguments: vec![OsString::from("service")],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        };
+        let access =
+            ServiceAccess::QUERY_STATUS | ServiceAccess::START | ServiceAccess::CHANGE_CONFIG;
+
+        // Installing again (e.g. after an update) re-points the existing service
+        let service = match manager.open_service(SERVICE_NAME, access) {
+            Ok(service) => {
+                service
+                    .change_config(&info)
+                    .map_err(|e| format!("Failed to update the service: {e}"))?;
+                service
+            }
+            Err(_) => manager
+                .create_service(&info, access)
+                .map_err(|e| format!("Failed to create the service: {e}"))?,
+        };
+
+        let running = service
+            .query_status()
+            .map(|s| s.current_state == ServiceState::Running)
+            .unwrap_or(false);
+        if !running {
+            service
+                .start(&[] as &[&OsStr])
+                .map_err(|e| format!("Failed to start the service: {e}"))?;
+        }
+        log("Service installed.");
+        Ok(())
+    }
+
+    fn is_stopped(service: &Service) -> bool {
+        service
+            .query_status()
+            .map(|s| s.current_state == ServiceState::Stopped)
+            .unwrap_or(true)
+    }
+
+    /// Stops the service if it's running, e.g. to replace its binary.
+    pub fn stop_service() -> Result<(), String> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .map_err(|e| format!("Failed to open the service manager: {e}"))?;
+        let access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP;
+        let service = match manager.open_service(SERVICE_NAME, access) {
+            Ok(service) => service,
+            // Not installed
+            Err(_) => return Ok(()),
+        };
+        if is_stopped(&service) {
+            return Ok(());
+        }
+
+        service
+            .stop()
+            .map_err(|e| format!("Failed to stop the service: {e}"))?;
+        for _ in 0..50 {
+            if is_stopped(&service) {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        Err("The helper service did not stop.".to_string())
+    }
+
+    pub fn uninstall_service() -> Result<(), String> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .map_err(|e| format!("Failed to open the service manager: {e}"))?;
+        let access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE;
+        let service = match manager.open_service(SERVICE_NAME, access) {
+            Ok(service) => service,
+            // Not installed
+            Err(_) => return Ok(()),
+        };
+
+        if !is_stopped(&service) {
+            let _ = service.stop();
+        }
+        service
+            .delete()
+            .map_err(|e| format!("Failed to delete the service: {e}"))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::path::Path;
+
+    pub fn run_service() -> Result<(), String> {
+        Err("`service` is only used on Windows; run `nera-helper serve` instead.".to_string())
+    }
+
+    /// There's no one init system to register with; the key is in place and
+    /// `nera-helper serve` (as root) is what a unit should run.
+    pub fn install_service(exe: &Path) -> Result<(), String> {
+        println!(
+            "Paired. Start the helper as root from your init system, e.g. a systemd unit running `{} serve`.",
+            exe.display()
+        );
+        Ok(())
+    }
+
+    // `serve` isn't ours to stop; the staged copy replaces the binary under it
+    pub fn stop_service() -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn uninstall_service() -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// --- Tauri Commands ---
+
+fn helper_exe() -> Result<PathBuf, NeraError> {
+    let exe = std::env::current_exe()
+        .map_err(|e| NeraError::Internal(format!("Can't locate the app: {e}")))?;
+    let helper = exe.with_file_name(if cfg!(windows) {
+        "nera-helper.exe"
+    } else {
+        "nera-helper"
+    });
+    if !helper.exists() {
+        return Err(NeraError::NotFound(format!(
+            "{} is missing next to the app.",
+            helper.display()
+        )));
+    }
+    Ok(helper)
+}
+
+/// Runs `nera-helper <args>` with administrator rights, asking the user.
+#[cfg(windows)]
+fn run_elevated(helper: &Path, args: &[String]) -> Result<(), NeraError> {
+    // Start-Process takes the arguments as one string, so each is quoted in it
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let args: Vec<String> = args.iter().map(|a| format!("\"{a}\"")).collect();
+    let script = format!(
+        "$p = Start-Process -FilePath {} -ArgumentList {} -Verb RunAs -Wait -PassThru -WindowStyle Hidden; exit $p.ExitCode",
+        quote(&helper.to_string_lossy()),
+        quote(&args.join(" ")),
+    );
+    let status = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .status()
+        .map_err(|e| NeraError::spawn_failed("powershell", e))?;
+    elevated_result(status)
+}
+
+#[cfg(not(windows))]
+fn run_elevated(helper: &Path, args: &[String]) -> Result<(), NeraError> {
+    let status = Command::new("pkexec")
+        .arg(helper)
+        .args(args)
+        .status()
+        .map_err(|e| NeraError::spawn_failed("pkexec", e))?;
+    elevated_result(status)
+}
+
+/// Runs the elevated installer, handing it the pairing key on stdin.
+#[cfg(not(windows))]
+fn run_install(helper: &Path, key: &str) -> Result<(), NeraError> {
+    let mut child = Command::new("pkexec")
+        .arg(helper)
+        .args(["install", "--pair", "-"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| NeraError::spawn_failed("pkexec", e))?;
+    // Fails if the prompt was cancelled, which the exit status reports
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(key.as_bytes());
+    }
+    let status = child
+        .wait()
+        .map_err(|e| NeraError::Internal(format!("Waiting for pkexec failed: {e}")))?;
+    elevated_result(status)
+}
+
+/// Runs the elevated installer, handing it the pairing key through a pipe.
+/// Start-Process can't redirect an elevated process's stdin.
+#[cfg(windows)]
+fn run_install(helper: &Path, key: &str) -> Result<(), NeraError> {
+    let pipe = pairing::KeyPipe::serve(key.to_string())?;
+    let args = [
+        "install".to_string(),
+        "--pair".to_string(),
+        pipe.name.clone(),
+    ];
+    let installed = run_elevated(helper, &args);
+    pipe.finish();
+    installed
+}
+
+#[cfg(windows)]
+mod pairing {
+    use std::{
+        fs::File,
+        io, mem, ptr,
+        thread::{self, JoinHandle},
+    };
+
+    use rand::RngCore;
+    use windows_sys::Win32::{
+        Foundation::{
+            CloseHandle, GetLastError, LocalFree, ERROR_PIPE_CONNECTED, HANDLE,
+            INVALID_HANDLE_VALUE,
+        },
+        Security::{
+            Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+        Storage::FileSystem::{
+            FlushFileBuffers, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_OUTBOUND,
+        },
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_WAIT,
+        },
+    };
+
+    // The user, SYSTEM and administrators; nothing inherited
+    const PIPE_SDDL: &str = "D:P(A;;GA;;;OW)(A;;GA;;;SY)(A;;GA;;;BA)";
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// A single-use local pipe that hands the key to the first reader.
+    pub struct KeyPipe {
+        pub name: String,
+        writer: JoinHandle<()>,
+    }
+
+    impl KeyPipe {
+        pub fn serve(key: String) -> Result<KeyPipe, String> {
+            let name = format!(r"\\.\pipe\nera-pair-{:016x}", rand::thread_rng().next_u64());
+
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            let converted = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    wide(PIPE_SDDL).as_ptr(),
+                    SDDL_REVISION_1,
+                    &mut descriptor,
+                    ptr::null_mut(),
+                )
+            };
+            if converted == 0 {
+                return Err(format!(
+                    "Failed to build the pairing pipe's ACL: {}",
+                    io::Error::last_os_error()
+                ));
+            }
+            let attributes = SECURITY_ATTRIBUTES {
+                nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: descriptor,
+                bInheritHandle: 0,
+            };
+            // First instance only, so nobody can have squatted on the name
+            let pipe = unsafe {
+                CreateNamedPipeW(
+                    wide(&name).as_ptr(),
+                    PIPE_ACCESS_OUTBOUND | FILE_FLAG_FIRST_PIPE_INSTANCE,
+                    PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    1,
+                    4096,
+                    4096,
+                    0,
+                    &attributes,
+                )
+            };
+            let created = io::Error::last_os_error();
+            unsafe { LocalFree(descriptor as _) };
+            if pipe == INVALID_HANDLE_VALUE {
+                return Err(format!("Failed to create the pairing pipe: {created}"));
+            }
+
+            let writer = thread::spawn(move || write_key(pipe, &key));
+            Ok(KeyPipe { name, writer })
+        }
+
+        /// Waits for the writer, unblocking it if the installer never
+        /// connected (e.g. the prompt was cancelled).
+        pub fn finish(self) {
+            let _ = File::open(&self.name);
+            let _ = self.writer.join();
+        }
+    }
+
+    fn write_key(pipe: HANDLE, key: &str) {
+        unsafe {
+            let connected = ConnectNamedPipe(pipe, ptr::null_mut()) != 0
+                || GetLastError() == ERROR_PIPE_CONNECTED;
+            if connected {
+                let mut written = 0;
+                if WriteFile(
+                    pipe,
+                    key.as_ptr(),
+                    key.len() as u32,
+                    &mut written,
+                    ptr::null_mut(),
+                ) != 0
+                {
+                    FlushFileBuffers(pipe);
+                }
+            }
+            CloseHandle(pipe);
+        }
+    }
+}
+
+fn elevated_result(status: std::process::ExitStatus) -> Result<(), NeraError> {
+    if status.success() {
+        return Ok(());
+    }
+    Err(NeraError::Internal(format!(
+        "The helper setup failed or was cancelled ({status}). See helper.log in {}.",
+        helper_dir_path().display()
+    )))
+}
+
+fn install_and_pair() -> Result<HelperStatus, NeraError> {
+    let helper = helper_exe()?;
+    let key = general_purpose::STANDARD.encode(random_bytes());
+
+    run_install(&helper, &key)?;
+
+    let sealed = secrets::seal(&key)?;
+    update_settings(|settings| settings.helper_key = Some(sealed));
+    append_log("Helper service installed and paired.").ok();
+
+    // Give the service a moment to come up
+    for _ in 0..10 {
+        let status = status();
+        if status.running {
+            return Ok(status);
+        }
+        thread::sleep(Duration::from_millis(300));
+    }
+    Ok(status())
+}
+
+fn uninstall_and_unpair() -> Result<HelperStatus, NeraError> {
+    let helper = helper_exe()?;
+    run_elevated(&helper, &["uninstall".to_string()])?;
+
+    update_settings(|settings| settings.helper_key = None);
+    append_log("Helper service removed.").ok();
+    Ok(status())
+}
+
+#[tauri::command]
+pub async fn get_helper_status() -> Result<HelperStatus, NeraError> {
+    tauri::async_runtime::spawn_blocking(status)
+        .await
+        .map_err(|e| NeraError::Internal(format!("Checking the helper failed: {e}")))
+}
+
+/// Installs the helper service and pairs it with this app. Asks for
+/// administrator rights once; after that the app runs without them.
+#[tauri::command]
+pub async fn install_helper() -> Result<HelperStatus, NeraError> {
+    tauri::async_runtime::spawn_blocking(install_and_pair)
+        .await
+        .map_err(|e| NeraError::Internal(format!("Installing the helper failed: {e}")))?
+}
+
+/// Removes the helper service, lifting the kill switch and any tunnel with
+/// it, and goes back to doing those operations in the app.
+#[tauri::command]
+pub async fn uninstall_helper() -> Result<HelperStatus, NeraError> {
+    tauri::async_runtime::spawn_blocking(uninstall_and_unpair)
+        .await
+        .map_err(|e| NeraError::Internal(format!("Removing the helper failed: {e}")))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs both sides of the handshake over loopback; `(helper, app)` results.
+    fn handshake(
+        helper_key: [u8; 32],
+        app_key: [u8; 32],
+    ) -> (Result<(), String>, Result<(), String>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let helper = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(AUTH_TIMEOUT)).unwrap();
+            authenticate_client(&helper_key, &mut BufReader::new(stream))
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(AUTH_TIMEOUT)).unwrap();
+        let app = authenticate_helper(&app_key, &mut BufReader::new(stream));
+        (helper.join().unwrap(), app)
+    }
+
+    fn catalog_endpoint() -> SocketAddr {
+        let server = &servers::SERVERS[0];
+        server.socket_addr_on(server.ports[0]).unwrap()
+    }
+
+    fn config(endpoint: &str, dns: &str) -> String {
+        format!(
+            "[Interface]\nPrivateKey = {key}\nAddress = 10.8.0.2/32\nDNS = {dns}\nMTU = 1420\n\n\
+             [Peer]\nPublicKey = {peer}\nAllowedIPs = 0.0.0.0/0, ::/0\nEndpoint = {endpoint}\n\
+             PersistentKeepalive = 25\n",
+            key = general_purpose::STANDARD.encode([7u8; 32]),
+            peer = servers::SERVERS[0].public_key,
+        )
+    }
+
+    #[test]
+    fn both_sides_authenticate_with_the_same_key() {
+        let key = random_bytes();
+        let (helper, app) = handshake(key, key);
+        assert_eq!(helper, Ok(()));
+        assert_eq!(app, Ok(()));
+    }
+
+    #[test]
+    fn a_different_key_fails_on_both_sides() {
+        let (helper, app) = handshake(random_bytes(), random_bytes());
+        assert!(helper.is_err());
+        assert!(app.is_err());
+    }
+
+    #[test]
+    fn scope_is_limited_to_catalog_endpoints_and_our_tunnels() {
+        let scope = |endpoint: SocketAddr, interface: &str| KillSwitchScope {
+            endpoints: vec![endpoint],
+            interfaces: vec![interface.to_string()],
+            relays: Vec::new(),
+        };
+        let relay = SocketAddr::from((Ipv4Addr::LOCALHOST, 51820));
+
+        assert!(check_scope(&scope(catalog_endpoint(), "nera")).is_ok());
+        assert!(check_scope(&scope(relay, "nera-hop")).is_ok());
+        assert!(check_scope(&scope("203.0.113.7:51820".parse().unwrap(), "nera")).is_err());
+        assert!(check_scope(&scope(catalog_endpoint(), "eth0")).is_err());
+    }
+
+    #[test]
+    fn relay_rules_only_reach_catalog_relays() {
+        // The relay rule isn't tied to a program, so this check is what keeps
+        // it from opening arbitrary TCP
+        let scope = |relay: SocketAddr| KillSwitchScope {
+            endpoints: Vec::new(),
+            interfaces: Vec::new(),
+            relays: vec![relay],
+        };
+        let catalog_relay = servers::SERVERS
+            .iter()
+            .find_map(|s| s.relay_addr())
+            .unwrap();
+
+        assert!(check_scope(&scope(catalog_relay)).is_ok());
+        assert!(check_scope(&scope("203.0.113.7:443".parse().unwrap())).is_err());
+        let other_port = SocketAddr::new(catalog_relay.ip(), 8443);
+        assert!(check_scope(&scope(other_port)).is_err());
+    }
+
+    #[test]
+    fn mtu_probes_only_reach_catalog_servers() {
+        assert!(check_probe_host(catalog_endpoint().ip()).is_ok());
+        assert!(check_probe_host("203.0.113.7".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn configs_must_point_at_the_catalog_and_our_dns() {
+        let endpoint = catalog_endpoint().to_string();
+        let ours = dns::listen_addr().ip().to_string();
+        assert!(check_config(&config(&endpoint, "1.1.1.1")).is_ok());
+        assert!(check_config(&config(&endpoint, &ours)).is_ok());
+        assert!(check_config(&config("127.0.0.1:40000", &ours)).is_ok());
+
+        assert!(check_config(&config("203.0.113.7:51820", "1.1.1.1")).is_err());
+        assert!(check_config(&config(&endpoint, "203.0.113.53")).is_err());
+        assert!(check_config(&config(&endpoint, "1.1.1.1, 203.0.113.53")).is_err());
+        let script = config(&endpoint, "1.1.1.1").replace("MTU", "PostUp = id\nMTU");
+        assert!(check_config(&script).is_err());
+    }
+
+    // Only requests the helper doesn't log, so nothing is written outside the tree
+    #[test]
+    fn dispatch_refuses_what_the_app_never_asks_for() {
+        let refused = |request: Value| {
+            let response = control::handle_line(&request.to_string(), dispatch).unwrap();
+            assert_eq!(response["error"]["code"], -32000, "{response}");
+            assert_eq!(response["error"]["data"]["code"], "invalid_input");
+        };
+        refused(json!({
+            "jsonrpc": "2.0", "id": 1, "method": "wg_show",
+            "params": { "interface": "nera", "field": "private-key" },
+        }));
+        refused(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "wg_show",
+            "params": { "interface": "../../etc/x", "field": "transfer" },
+        }));
+    }
+}
@@ -0,0 +1,290 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Multiple accounts side by side.
+//
+// The active identity keeps living in the top-level settings fields everything
+// else already reads (private_key, device_ip, auth_session, ...). Inactive ones
+// are stashed in `identities`; switching swaps the two after a clean disconnect.
+// The kill switch isn't touched, so it stays on across the switch if it was on.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::{
+    api_client::AuthSession,
+    append_log, disconnect_vpn_internal,
+    error::NeraError,
+    events,
+    key_rotation::PendingRotation,
+    load_settings,
+    multihop::HopKey,
+    provisioning::{self, ProvisioningState},
+    psk,
+    session_journal::DisconnectReason,
+    update_settings, AppSettings, VpnState,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    // Sealed, as in settings
+    pub private_key: String,
+    pub public_key: String,
+    pub device_ip: String,
+    #[serde(default)]
+    pub auth_session: Option<AuthSession>,
+    #[serde(default)]
+    pub key_created_at: Option<String>,
+    #[serde(default)]
+    pub pending_rotation: Option<PendingRotation>,
+    #[serde(default)]
+    pub provisioning: ProvisioningState,
+    // Sealed, as in settings
+    #[serde(default)]
+    pub preshared_keys: BTreeMap<String, String>,
+    #[serde(default)]
+    pub psk_rotated_at: Option<String>,
+    #[serde(default)]
+    pub hop_key: Option<HopKey>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IdentitySummary {
+    pub id: String,
+    pub email: Option<String>,
+    pub public_key: String,
+    pub device_ip: String,
+    pub signed_in: bool,
+    pub active: bool,
+}
+
+impl IdentitySummary {
+    fn of(identity: &Identity, active: bool) -> Self {
+        IdentitySummary {
+            id: identity.id.clone(),
+            email: identity.email.clone(),
+            public_key: identity.public_key.clone(),
+            device_ip: identity.device_ip.clone(),
+            signed_in: identity.auth_session.is_some()
+                && identity.provisioning == ProvisioningState::Ready,
+            active,
+        }
+    }
+}
+
+pub fn new_identity_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// The active identity as stored in the top-level fields, if there is one.
+fn active(settings: &AppSettings) -> Option<Identity> {
+    if settings.public_key.is_empty() {
+        return None;
+    }
+    Some(Identity {
+        // Only settings from before identities lack an id
+        id: settings
+            .active_identity_id
+            .clone()
+            .unwrap_or_else(new_identity_id),
+        email: settings.account_email.clone(),
+        private_key: settings.private_key.clone(),
+        public_key: settings.public_key.clone(),
+        device_ip: settings.device_ip.clone(),
+        auth_session: settings.auth_session.clone(),
+        key_created_at: settings.key_created_at.clone(),
+        pending_rotation: settings.pending_rotation.clone(),
+        provisioning: provisioning::current(settings),
+        preshared_keys: settings.preshared_keys.clone(),
+        psk_rotated_at: settings.psk_rotated_at.clone(),
+        hop_key: settings.hop_key.clone(),
+    })
+}
+
+fn make_active(settings: &mut AppSettings, identity: Option<Identity>) {
+    match identity {
+        Some(i) => {
+            settings.active_identity_id = Some(i.id);
+            settings.account_email = i.email;
+            settings.private_key = i.private_key;
+            settings.public_key = i.public_key;
+            settings.device_ip = i.device_ip;
+            settings.auth_session = i.auth_session;
+            settings.key_created_at = i.key_created_at;
+            settings.pending_rotation = i.pending_rotation;
+            settings.provisioning = i.provisioning;
+            settings.preshared_keys = i.preshared_keys;
+            settings.psk_rotated_at = i.psk_rotated_at;
+            settings.hop_key = i.hop_key;
+        }
+        None => {
+            settings.active_identity_id = None;
+            settings.account_email = None;
+            settings.private_key = String::new();
+            settings.public_key = String::new();
+            settings.device_ip = String::new();
+            settings.auth_session = None;
+            settings.key_created_at = None;
+            settings.pending_rotation = None;
+            settings.hop_key = None;
+            settings.provisioning = ProvisioningState::Unprovisioned;
+            psk::clear(settings);
+        }
+    }
+}
+
+fn summaries(settings: &AppSettings) -> Vec<IdentitySummary> {
+    let mut list: Vec<IdentitySummary> = active(settings)
+        .iter()
+        .map(|i| IdentitySummary::of(i, true))
+        .collect();
+    list.extend(
+        settings
+            .identities
+            .iter()
+            .map(|i| IdentitySummary::of(i, false)),
+    );
+    list
+}
+
+/// Stashes the active identity and activates `target` (or nothing, to make
+/// room for a new sign-in). Disconnects first; no key rotation may be running.
+fn swap_active(
+    app: &AppHandle,
+    state: &State<VpnState>,
+    target: Option<&str>,
+) -> Result<Vec<IdentitySummary>, NeraError> {
+    let _guard = state.rotation_lock.try_lock().map_err(|_| {
+        NeraError::Busy("A key rotation is in progress; try again shortly.".to_string())
+    })?;
+
+    let unknown = |id: &str| NeraError::NotFound(format!("Unknown identity: {id}"));
+    if let Some(id) = target {
+        if !load_settings().identities.iter().any(|i| i.id == id) {
+            return Err(unknown(id));
+        }
+    }
+
+    if *state.connected.lock().unwrap() {
+        disconnect_vpn_internal(Some(app), state, DisconnectReason::IdentitySwitch)?;
+    }
+
+    // Swapped on fresh settings: the disconnect and token refreshes write too
+    update_settings(|settings| {
+        let next = match target {
+            Some(id) => {
+                let index = settings
+                    .identities
+                    .iter()
+                    .position(|i| i.id == id)
+                    .ok_or_else(|| unknown(id))?;
+                Some(settings.identities.remove(index))
+            }
+            None => None,
+        };
+        if let Some(current) = active(settings) {
+            settings.identities.push(current);
+        }
+        make_active(settings, next);
+
+        // A fresh slot gets a keypair straight away so sign-up can use it
+        if target.is_none() {
+            provisioning::generate_key(settings)?;
+            settings.active_identity_id = Some(new_identity_id());
+        }
+        Ok::<_, NeraError>(())
+    })?;
+    state.api.reload_session();
+    let settings = load_settings();
+
+    append_log(&format!(
+        "Identity switched to {}",
+        settings
+            .account_email
+            .as_deref()
+            .unwrap_or("(new identity)")
+    ))
+    .ok();
+    events::emit(app, "identity-changed", settings.active_identity_id.clone()).ok();
+    Ok(summaries(&settings))
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn list_identities() -> Vec<IdentitySummary> {
+    summaries(&load_settings())
+}
+
+#[tauri::command]
+pub fn switch_identity(
+    app: AppHandle,
+    state: State<'_, VpnState>,
+    id: String,
+) -> Result<Vec<IdentitySummary>, NeraError> {
+    if load_settings().active_identity_id.as_deref() == Some(id.as_str()) {
+        return Ok(list_identities());
+    }
+    swap_active(&app, &state, Some(&id))
+}
+
+/// Keeps the current identity and starts a blank one to sign in with.
+#[tauri::command]
+pub fn add_identity(
+    app: AppHandle,
+    state: State<'_, VpnState>,
+) -> Result<Vec<IdentitySummary>, NeraError> {
+    swap_active(&app, &state, None)
+}
+
+/// Forgets an inactive identity (sign out to drop the active one) and
+/// revokes its session on a best-effort basis.
+#[tauri::command]
+pub async fn remove_identity(
+    state: State<'_, VpnState>,
+    id: String,
+) -> Result<Vec<IdentitySummary>, NeraError> {
+    let removed = update_settings(|settings| {
+        if settings.active_identity_id.as_deref() == Some(id.as_str()) {
+            return Err(NeraError::InvalidInput(
+                "Can't remove the active identity. Switch or sign out first.".to_string(),
+            ));
+        }
+        let index = settings
+            .identities
+            .iter()
+            .position(|i| i.id == id)
+            .ok_or_else(|| NeraError::NotFound(format!("Unknown identity: {id}")))?;
+        Ok(settings.identities.remove(index))
+    })?;
+
+    append_log(&format!(
+        "Identity removed: {}",
+        removed.email.as_deref().unwrap_or(&removed.id)
+    ))
+    .ok();
+
+    if let Some(session) = removed.auth_session.and_then(|s| s.open().ok()) {
+        let api = state.api.clone();
+        let revoked = tauri::async_runtime::spawn_blocking(move || api.revoke_session(&session))
+            .await
+            .map_err(|e| NeraError::Internal(format!("Removing identity failed: {e}")))?;
+        if let Err(e) = revoked {
+            append_log(&format!("Could not revoke removed identity's session: {e}")).ok();
+        }
+    }
+
+    Ok(summaries(&load_settings()))
+}
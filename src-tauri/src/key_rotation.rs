@@ -0,0 +1,496 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Scheduled WireGuard key rotation.
+//
+// Make-before-break in three persisted stages, so a crash at any point resumes
+// where it stopped instead of losing a key:
+//   generated  - new keypair saved locally, server doesn't know it yet
+//   registered - server accepts both keys; switch the tunnel to the new one
+//   switched   - device uses the new key; revoke the old one, then done
+// A live tunnel is switched in place with `wg set`, so it never goes down. If
+// the server hands out a different address the switch waits for the next connect.
+
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    api_client::{RotateKeyRequest, RotateKeyResponse},
+    append_log,
+    error::NeraError,
+    generate_keypair, load_settings, provisioning, psk, secrets, traffic, update_settings,
+    AppSettings, VpnState,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const SWITCH_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(45);
+const MAX_INTERVAL_DAYS: u32 = 365;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStage {
+    Generated,
+    Registered,
+    Switched,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingRotation {
+    pub stage: RotationStage,
+    pub started_at: String,
+    /// Sealed, like the device key in settings.
+    pub new_private_key: String,
+    pub new_public_key: String,
+    #[serde(default)]
+    pub new_device_ip: Option<String>,
+    pub old_public_key: String,
+    /// PSKs issued for the new key, sealed.
+    #[serde(default)]
+    pub new_preshared_keys: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct KeyRotationStatus {
+    pub interval_days: Option<u32>,
+    pub key_created_at: Option<String>,
+    pub next_rotation_at: Option<String>,
+    pub pending_stage: Option<RotationStage>,
+}
+
+fn save_pending(pending: Option<&PendingRotation>) {
+    update_settings(|settings| settings.pending_rotation = pending.cloned());
+}
+
+fn next_rotation_at(settings: &AppSettings) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let days = settings.key_rotation_days?;
+    let created = chrono::DateTime::parse_from_rfc3339(settings.key_created_at.as_deref()?).ok()?;
+    Some(created + chrono::Duration::days(days as i64))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Makes the new key the device's key in settings and on-disk configs.
+fn commit_new_key(pending: &PendingRotation) {
+    update_settings(|settings| {
+        settings.private_key = pending.new_private_key.clone();
+        settings.public_key = pending.new_public_key.clone();
+        if let Some(ip) = &pending.new_device_ip {
+            settings.device_ip = ip.clone();
+        }
+        settings.key_created_at = Some(chrono::Local::now().to_rfc3339());
+        // PSKs belong to the key, so they're replaced along with it
+        settings.preshared_keys = pending.new_preshared_keys.clone();
+        settings.psk_rotated_at =
+            Some(chrono::Local::now().to_rfc3339()).filter(|_| !settings.preshared_keys.is_empty());
+    });
+}
+
+/// Moves the device to the new key. Returns `false` if that has to wait for
+/// the next connect.
+fn switch_key(state: &VpnState, pending: &PendingRotation) -> Result<bool, String> {
+    // A connect or disconnect mid-swap would bring up the wrong key
+    let _transition = state.transition_lock.lock().unwrap();
+    let settings = load_settings();
+    let connected = *state.connected.lock().unwrap();
+    // Only the outer tunnel uses the device key; a multi-hop inner tunnel
+    // has its own
+    let interfaces: Vec<String> = state
+        .active_scope
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|s| s.interfaces.first().cloned())
+        .into_iter()
+        .collect();
+
+    if !connected || interfaces.is_empty() {
+        commit_new_key(pending);
+        return Ok(true);
+    }
+
+    let same_address = pending
+        .new_device_ip
+        .as_deref()
+        .map_or(true, |ip| ip == settings.device_ip);
+    if !same_address {
+        append_log("Key rotation: new address assigned; switching on next connect.").ok();
+        return Ok(false);
+    }
+
+    // Live swap. The old key stays valid server-side until we revoke it, so
+    // if the new one can't be applied or doesn't handshake we put it back.
+    let new_key = secrets::open(&pending.new_private_key)?;
+    let since = unix_now();
+    let applied = interfaces
+        .iter()
+        .try_for_each(|interface| traffic::set_private_key(interface, &new_key))
+        .and_then(|_| psk::apply_live(&interfaces, &pending.new_preshared_keys));
+    if let Err(e) = applied {
+        restore_old_key(&interfaces, &settings);
+        return Err(format!(
+            "Could not apply the new key ({e}); kept the old one."
+        ));
+    }
+
+    if traffic::wait_for_handshake_since(&interfaces[0], since, SWITCH_HANDSHAKE_TIMEOUT) {
+        commit_new_key(pending);
+        append_log("Key rotation: tunnel switched to the new key.").ok();
+        Ok(true)
+    } else {
+        restore_old_key(&interfaces, &settings);
+        Err("No handshake with the new key; kept the old one.".to_string())
+    }
+}
+
+// Best effort: the tunnel is already failing if this doesn't work either
+fn restore_old_key(interfaces: &[String], settings: &AppSettings) {
+    let restored = provisioning::private_key(settings).and_then(|old_key| {
+        interfaces
+            .iter()
+            .try_for_each(|interface| traffic::set_private_key(interface, &old_key))
+    });
+    if let Err(e) = restored {
+        append_log(&format!("Key rotation: could not restore the old key: {e}")).ok();
+    }
+    if let Err(e) = psk::apply_live(interfaces, &settings.preshared_keys) {
+        append_log(&format!(
+            "Key rotation: could not restore the old PSKs: {e}"
+        ))
+        .ok();
+    }
+}
+
+/// Applies a registered-but-not-switched key before a connect, when there's
+/// no tunnel to keep up. The caller holds `transition_lock`, so a rotation
+/// that's running is left to finish on its own rather than waited for.
+pub fn switch_before_connect(state: &VpnState) {
+    let _guard = match state.rotation_lock.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    let mut pending = match load_settings().pending_rotation {
+        Some(p) if p.stage == RotationStage::Registered => p,
+        _ => return,
+    };
+    commit_new_key(&pending);
+    pending.stage = RotationStage::Switched;
+    save_pending(Some(&pending));
+    append_log("Key rotation: switched to the new key before connecting.").ok();
+}
+
+/// Runs (or resumes) a rotation through to the end.
+pub fn rotate(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<VpnState>();
+    let _guard = state
+        .rotation_lock
+        .try_lock()
+        .map_err(|_| "Key rotation is already in progress.".to_string())?;
+
+    let settings = load_settings();
+    let pending = match settings.pending_rotation {
+        Some(p) => {
+            append_log(&format!("Key rotation: resuming at stage {:?}", p.stage)).ok();
+            p
+        }
+        None => {
+            if settings.public_key.is_empty() || settings.device_ip.is_empty() {
+                return Err("Key rotation needs a registered device.".to_string());
+            }
+            let (private_key, public_key) = generate_keypair();
+            let pending = PendingRotation {
+                stage: RotationStage::Generated,
+                started_at: chrono::Local::now().to_rfc3339(),
+                new_private_key: secrets::seal(&private_key)?,
+                new_public_key: public_key,
+                new_device_ip: None,
+                old_public_key: settings.public_key.clone(),
+                new_preshared_keys: BTreeMap::new(),
+            };
+            // Saved before the server hears of it, so a crash can't orphan the key
+            save_pending(Some(&pending));
+            append_log("Key rotation: new keypair generated.").ok();
+            pending
+        }
+    };
+
+    run_stages(pending, &LiveStages { state: &state })
+}
+
+/// What each stage does outside this module; tests drive the stages with
+/// their own.
+trait Stages {
+    fn register(&self, request: &RotateKeyRequest) -> Result<RotateKeyResponse, String>;
+    fn switch(&self, pending: &PendingRotation) -> Result<bool, String>;
+    fn revoke(&self, old_public_key: &str) -> Result<(), String>;
+    fn save(&self, pending: Option<&PendingRotation>);
+}
+
+struct LiveStages<'a> {
+    state: &'a VpnState,
+}
+
+impl Stages for LiveStages<'_> {
+    fn register(&self, request: &RotateKeyRequest) -> Result<RotateKeyResponse, String> {
+        self.state
+            .api
+            .rotate_key(request)
+            .map_err(|e| e.to_string())
+    }
+
+    fn switch(&self, pending: &PendingRotation) -> Result<bool, String> {
+        switch_key(self.state, pending)
+    }
+
+    fn revoke(&self, old_public_key: &str) -> Result<(), String> {
+        self.state
+            .api
+            .revoke_key(old_public_key)
+            .map_err(|e| e.to_string())
+    }
+
+    fn save(&self, pending: Option<&PendingRotation>) {
+        save_pending(pending);
+    }
+}
+
+/// Takes `pending` from its saved stage to the end, saving after each one.
+fn run_stages(mut pending: PendingRotation, stages: &impl Stages) -> Result<(), String> {
+    loop {
+        match pending.stage {
+            RotationStage::Generated => {
+                let response = stages
+                    .register(&RotateKeyRequest {
+                        old_public_key: pending.old_public_key.clone(),
+                        new_public_key: pending.new_public_key.clone(),
+                    })
+                    .map_err(|e| format!("Key rotation: registering new key failed: {e}"))?;
+                pending.new_device_ip = Some(response.ip);
+                pending.new_preshared_keys = psk::seal_all(&response.preshared_keys)?;
+                pending.stage = RotationStage::Registered;
+                stages.save(Some(&pending));
+                append_log("Key rotation: new key registered.").ok();
+            }
+            RotationStage::Registered => {
+                if !stages
+                    .switch(&pending)
+                    .map_err(|e| format!("Key rotation: switch failed: {e}"))?
+                {
+                    return Ok(());
+                }
+                pending.stage = RotationStage::Switched;
+                stages.save(Some(&pending));
+            }
+            RotationStage::Switched => {
+                stages
+                    .revoke(&pending.old_public_key)
+                    .map_err(|e| format!("Key rotation: revoking old key failed: {e}"))?;
+                stages.save(None);
+                append_log("Key rotation: old key revoked. Rotation complete.").ok();
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Finishes an interrupted rotation at startup, then rotates whenever the key
+/// is older than the configured interval. Preshared keys follow their own.
+pub fn spawn_rotation_scheduler(app: AppHandle) {
+    thread::spawn(move || loop {
+        // Keys from before rotation existed count from now
+        update_settings(|settings| {
+            if settings.key_created_at.is_none() && !settings.public_key.is_empty() {
+                settings.key_created_at = Some(chrono::Local::now().to_rfc3339());
+            }
+        });
+        let settings = load_settings();
+
+        let due = next_rotation_at(&settings).map_or(false, |at| chrono::Local::now() >= at);
+        let has_session = app.state::<VpnState>().api.has_session();
+
+        if has_session && (settings.pending_rotation.is_some() || due) {
+            if let Err(e) = rotate(&app) {
+                append_log(&e).ok();
+            }
+        } else if has_session && psk::is_due(&settings) {
+            if let Err(e) = psk::rotate(&app) {
+                append_log(&e).ok();
+            }
+        }
+        thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_key_rotation() -> KeyRotationStatus {
+    let settings = load_settings();
+    KeyRotationStatus {
+        interval_days: settings.key_rotation_days,
+        next_rotation_at: next_rotation_at(&settings).map(|at| at.to_rfc3339()),
+        key_created_at: settings.key_created_at,
+        pending_stage: settings.pending_rotation.map(|p| p.stage),
+    }
+}
+
+/// Rotates every `days` days; `None` turns scheduled rotation off.
+#[tauri::command]
+pub fn set_key_rotation_interval(days: Option<u32>) -> Result<KeyRotationStatus, NeraError> {
+    if let Some(d) = days {
+        if d == 0 || d > MAX_INTERVAL_DAYS {
+            return Err(NeraError::InvalidInput(format!(
+                "Rotation interval must be between 1 and {MAX_INTERVAL_DAYS} days."
+            )));
+        }
+    }
+
+    update_settings(|settings| settings.key_rotation_days = days);
+
+    append_log(&format!("Key rotation interval set to {days:?} days")).ok();
+    Ok(get_key_rotation())
+}
+
+#[tauri::command]
+pub async fn rotate_key_now(app: AppHandle) -> Result<KeyRotationStatus, NeraError> {
+    tauri::async_runtime::spawn_blocking(move || rotate(&app))
+        .await
+        .map_err(|e| format!("Key rotation failed: {e}"))??;
+    Ok(get_key_rotation())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct FakeStages {
+        deferred: bool,
+        calls: RefCell<Vec<&'static str>>,
+        saved: RefCell<Vec<Option<RotationStage>>>,
+    }
+
+    impl Stages for FakeStages {
+        fn register(&self, request: &RotateKeyRequest) -> Result<RotateKeyResponse, String> {
+            assert_eq!(request.old_public_key, "old");
+            self.calls.borrow_mut().push("register");
+            Ok(RotateKeyResponse {
+                ip: "10.0.0.9".to_string(),
+                preshared_keys: BTreeMap::new(),
+            })
+        }
+
+        fn switch(&self, pending: &PendingRotation) -> Result<bool, String> {
+            assert_eq!(pending.stage, RotationStage::Registered);
+            self.calls.borrow_mut().push("switch");
+            Ok(!self.deferred)
+        }
+
+        fn revoke(&self, old_public_key: &str) -> Result<(), String> {
+            assert_eq!(old_public_key, "old");
+            self.calls.borrow_mut().push("revoke");
+            Ok(())
+        }
+
+        fn save(&self, pending: Option<&PendingRotation>) {
+            self.saved.borrow_mut().push(pending.map(|p| p.stage));
+        }
+    }
+
+    fn pending(stage: RotationStage) -> PendingRotation {
+        PendingRotation {
+            stage,
+            started_at: "2026-01-01T00:00:00+00:00".to_string(),
+            new_private_key: String::new(),
+            new_public_key: "new".to_string(),
+            new_device_ip: None,
+            old_public_key: "old".to_string(),
+            new_preshared_keys: BTreeMap::new(),
+        }
+    }
+
+    fn resume(
+        stage: RotationStage,
+        stages: &FakeStages,
+    ) -> (Vec<&'static str>, Vec<Option<RotationStage>>) {
+        run_stages(pending(stage), stages).unwrap();
+        (stages.calls.take(), stages.saved.take())
+    }
+
+    #[test]
+    fn resumes_from_generated() {
+        let (calls, saved) = resume(RotationStage::Generated, &FakeStages::default());
+        assert_eq!(calls, ["register", "switch", "revoke"]);
+        assert_eq!(
+            saved,
+            [
+                Some(RotationStage::Registered),
+                Some(RotationStage::Switched),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn resumes_from_registered_without_registering_again() {
+        let (calls, saved) = resume(RotationStage::Registered, &FakeStages::default());
+        assert_eq!(calls, ["switch", "revoke"]);
+        assert_eq!(saved, [Some(RotationStage::Switched), None]);
+    }
+
+    #[test]
+    fn resumes_from_switched_by_revoking_only() {
+        let (calls, saved) = resume(RotationStage::Switched, &FakeStages::default());
+        assert_eq!(calls, ["revoke"]);
+        assert_eq!(saved, [None]);
+    }
+
+    #[test]
+    fn a_deferred_switch_stays_registered() {
+        let stages = FakeStages {
+            deferred: true,
+            ..FakeStages::default()
+        };
+        let (calls, saved) = resume(RotationStage::Generated, &stages);
+        assert_eq!(calls, ["register", "switch"]);
+        assert_eq!(saved, [Some(RotationStage::Registered)]);
+    }
+
+    #[test]
+    fn next_rotation_counts_from_key_creation() {
+        let mut settings = AppSettings::default();
+        assert_eq!(next_rotation_at(&settings), None);
+
+        settings.key_rotation_days = Some(30);
+        assert_eq!(next_rotation_at(&settings), None);
+
+        settings.key_created_at = Some("2026-01-01T12:00:00+02:00".to_string());
+        let at = next_rotation_at(&settings).unwrap();
+        assert_eq!(at.to_rfc3339(), "2026-01-31T12:00:00+02:00");
+
+        settings.key_created_at = Some("not a date".to_string());
+        assert_eq!(next_rotation_at(&settings), None);
+
+        settings.key_created_at = Some("2026-01-01T12:00:00+02:00".to_string());
+        settings.key_rotation_days = None;
+        assert_eq!(next_rotation_at(&settings), None);
+    }
+}
@@ -0,0 +1,457 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Native latency probing.
+//
+// Replaces shelling out to `ping`. We send ICMP echo where the OS lets us open an
+// ICMP socket, and otherwise time a TCP connect (a refused connection still
+// measures a full round trip). The endpoint and the in-tunnel gateway are probed
+// separately on their own thread and summarized over a sliding window. The
+// kill switch only lets WireGuard itself reach the endpoint, so while it's on
+// the endpoint isn't probed and the gateway stands in for it.
+
+use std::{
+    collections::VecDeque,
+    io,
+    mem::MaybeUninit,
+    net::{IpAddr, SocketAddr, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tauri::{AppHandle, Manager};
+
+use crate::{events, VpnState};
+
+const WINDOW_SIZE: usize = 30;
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeMethod {
+    Icmp,
+    Tcp,
+}
+
+/// Summary of the last `WINDOW_SIZE` probes. RTT fields are `None` when every
+/// probe in the window was lost.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LatencyStats {
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub loss_pct: f64,
+    pub samples: usize,
+    pub method: Option<ProbeMethod>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LatencyReport {
+    /// `None` while the kill switch keeps probes from reaching the endpoint.
+    pub endpoint: Option<LatencyStats>,
+    pub gateway: Option<LatencyStats>,
+}
+
+impl LatencyReport {
+    /// Short label for the traffic display, e.g. "24 ms". Falls back to the
+    /// gateway, whose round trip includes the endpoint's.
+    pub fn ping_label(&self) -> String {
+        let avg = |stats: &Option<LatencyStats>| stats.as_ref().and_then(|s| s.avg_ms);
+        match avg(&self.endpoint).or_else(|| avg(&self.gateway)) {
+            Some(ms) if ms < 1.0 => "<1 ms".to_string(),
+            Some(ms) => format!("{} ms", ms.round() as u64),
+            None => "—".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LatencyWindow {
+    samples: VecDeque<Option<f64>>,
+    method: Option<ProbeMethod>,
+}
+
+impl LatencyWindow {
+    pub fn push(&mut self, sample: Option<(Duration, ProbeMethod)>) {
+        if self.samples.len() == WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+        match sample {
+            Some((rtt, method)) => {
+                self.samples.push_back(Some(rtt.as_secs_f64() * 1000.0));
+                self.method = Some(method);
+            }
+            None => self.samples.push_back(None),
+        }
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let received: Vec<f64> = self.samples.iter().flatten().copied().collect();
+        let total = self.samples.len();
+        let loss_pct = if total == 0 {
+            0.0
+        } else {
+            (total - received.len()) as f64 * 100.0 / total as f64
+        };
+
+        if received.is_empty() {
+            return LatencyStats {
+                loss_pct,
+                samples: total,
+                method: self.method,
+                ..Default::default()
+            };
+        }
+
+        let min = received.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = received.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let avg = received.iter().sum::<f64>() / received.len() as f64;
+
+        // Mean absolute difference between consecutive replies (RFC 3550 style)
+        let jitter = if received.len() > 1 {
+            let diffs: f64 = received.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+            Some(diffs / (received.len() - 1) as f64)
+        } else {
+            None
+        };
+
+        LatencyStats {
+            min_ms: Some(min),
+            avg_ms: Some(avg),
+            max_ms: Some(max),
+            jitter_ms: jitter,
+            loss_pct,
+            samples: total,
+            method: self.method,
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            u16::from_be_bytes([chunk[0], chunk[1]])
+        } else {
+            u16::from_be_bytes([chunk[0], 0])
+        };
+        sum = sum.wrapping_add(word as u32);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn open_icmp_socket(ip: &IpAddr) -> io::Result<Socket> {
+    let (domain, protocol) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    // Raw sockets need admin (which the Windows app already has); the datagram
+    // flavour is the unprivileged "ping socket" on Linux/macOS.
+    Socket::new(domain, Type::RAW, Some(protocol))
+        .or_else(|_| Socket::new(domain, Type::DGRAM, Some(protocol)))
+}
+
+/// Sets Don't Fragment on outgoing packets (for IPv6: no local fragmentation).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_dont_fragment(socket: &Socket, ip: &IpAddr) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name, value) = match ip {
+        IpAddr::V4(_) => (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        ),
+        IpAddr::V6(_) => (
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MTU_DISCOVER,
+            libc::IPV6_PMTUDISC_DO,
+        ),
+    };
+    // SAFETY: the fd is open for the duration of the call and `value` is a
+    // c_int, as the option expects.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn set_dont_fragment(socket: &Socket, ip: &IpAddr) -> io::Result<()> {
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{
+        setsockopt, IPPROTO_IP, IPPROTO_IPV6, IPV6_DONTFRAG, IP_DONTFRAGMENT,
+    };
+
+    let (level, name) = match ip {
+        IpAddr::V4(_) => (IPPROTO_IP, IP_DONTFRAGMENT),
+        IpAddr::V6(_) => (IPPROTO_IPV6, IPV6_DONTFRAG),
+    };
+    let value: u32 = 1;
+    // SAFETY: the socket is open for the duration of the call and `value` is
+    // the DWORD the option expects.
+    let result = unsafe {
+        setsockopt(
+            socket.as_raw_socket() as usize,
+            level,
+            name,
+            &value as *const u32 as *const u8,
+            std::mem::size_of::<u32>() as i32,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn set_dont_fragment(_socket: &Socket, _ip: &IpAddr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Don't Fragment probing is not supported on this platform",
+    ))
+}
+
+/// Sends one ICMP echo request with `payload` bytes of data and waits for the
+/// matching reply. With `dont_fragment`, a packet too big for the path gets
+/// no reply at all.
+fn echo(
+    ip: IpAddr,
+    payload: usize,
+    dont_fragment: bool,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    let socket = open_icmp_socket(&ip)?;
+    if dont_fragment {
+        set_dont_fragment(&socket, &ip)?;
+    }
+    let seq = rand::random::<u16>();
+    let (request, reply) = match ip {
+        IpAddr::V4(_) => (8u8, 0u8),
+        IpAddr::V6(_) => (128u8, 129u8),
+    };
+
+    let mut packet = vec![request, 0, 0, 0];
+    packet.extend_from_slice(&rand::random::<u16>().to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend(b"nera-latency-probe".iter().cycle().take(payload));
+    // The stack fills in the ICMPv6 checksum (it covers a pseudo-header)
+    if ip.is_ipv4() {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    let dest = SocketAddr::new(ip, 0);
+    let started = Instant::now();
+    socket.send_to(&packet, &dest.into())?;
+
+    let mut buf = vec![MaybeUninit::<u8>::uninit(); payload + 128];
+    loop {
+        let remaining = timeout
+            .checked_sub(started.elapsed())
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "ICMP echo timed out"))?;
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (len, from) = socket.recv_from(&mut buf)?;
+        // SAFETY: recv_from initialized the first `len` bytes.
+        let data: Vec<u8> = buf[..len]
+            .iter()
+            .map(|b| unsafe { b.assume_init() })
+            .collect();
+
+        if from.as_socket().map(|a| a.ip()) != Some(ip) {
+            continue;
+        }
+
+        // Raw IPv4 sockets hand us the IP header too; ping sockets and IPv6
+        // sockets don't.
+        let icmp = if ip.is_ipv4() && !data.is_empty() && data[0] >> 4 == 4 {
+            let header_len = ((data[0] & 0x0f) as usize) * 4;
+            match data.get(header_len..) {
+                Some(rest) => rest,
+                None => continue,
+            }
+        } else {
+            &data[..]
+        };
+
+        // Echo reply with our sequence number (ping sockets rewrite the ident)
+        if icmp.len() >= 8 && icmp[0] == reply && icmp[6..8] == seq.to_be_bytes() {
+            return Ok(started.elapsed());
+        }
+    }
+}
+
+/// Sends one ICMP echo request and waits for the matching reply.
+pub fn icmp_echo(ip: IpAddr, timeout: Duration) -> io::Result<Duration> {
+    echo(ip, 18, false, timeout)
+}
+
+/// One echo with Don't Fragment set and `payload` bytes of data; `Ok` only
+/// if it came back whole.
+pub fn icmp_echo_df(ip: IpAddr, payload: usize, timeout: Duration) -> io::Result<Duration> {
+    echo(ip, payload, true, timeout)
+}
+
+/// Times a TCP handshake. A refused connection is still a round trip, so it counts.
+pub fn tcp_connect_rtt(addr: SocketAddr, timeout: Duration) -> io::Result<Duration> {
+    let started = Instant::now();
+    match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(_) => Ok(started.elapsed()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(started.elapsed()),
+        Err(e) => Err(e),
+    }
+}
+
+/// One RTT sample, ICMP first and TCP connect timing as the fallback.
+pub fn probe_rtt(addr: SocketAddr, timeout: Duration) -> Option<(Duration, ProbeMethod)> {
+    match icmp_echo(addr.ip(), timeout) {
+        Ok(rtt) => Some((rtt, ProbeMethod::Icmp)),
+        Err(_) => tcp_connect_rtt(addr, timeout)
+            .ok()
+            .map(|rtt| (rtt, ProbeMethod::Tcp)),
+    }
+}
+
+/// Probes `endpoint` and (optionally) the in-tunnel `gateway` until `flag` is cleared,
+/// publishing into `report` and emitting `latency-update` after every round.
+pub fn spawn_latency_monitor(
+    app: AppHandle,
+    flag: Arc<AtomicBool>,
+    report: Arc<Mutex<LatencyReport>>,
+    endpoint: Option<SocketAddr>,
+    gateway: Option<SocketAddr>,
+) {
+    *report.lock().unwrap() = LatencyReport::default();
+
+    thread::spawn(move || {
+        let mut endpoint_window = LatencyWindow::default();
+        let mut gateway_window = LatencyWindow::default();
+
+        while flag.load(Ordering::Relaxed) {
+            let round_started = Instant::now();
+
+            let blocked = *app.state::<VpnState>().kill_switch_enabled.lock().unwrap();
+            let endpoint_stats = match endpoint {
+                // Every probe would count as lost
+                Some(_) if blocked => {
+                    endpoint_window = LatencyWindow::default();
+                    None
+                }
+                Some(addr) => {
+                    endpoint_window.push(probe_rtt(addr, PROBE_TIMEOUT));
+                    Some(endpoint_window.stats())
+                }
+                None => None,
+            };
+            let gateway_stats = gateway.map(|addr| {
+                gateway_window.push(probe_rtt(addr, PROBE_TIMEOUT));
+                gateway_window.stats()
+            });
+
+            let snapshot = LatencyReport {
+                endpoint: endpoint_stats,
+                gateway: gateway_stats,
+            };
+            *report.lock().unwrap() = snapshot.clone();
+            events::emit_transient(&app, "latency-update", snapshot).ok();
+
+            if let Some(rest) = PROBE_INTERVAL.checked_sub(round_started.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(samples: &[Option<u64>]) -> LatencyWindow {
+        let mut window = LatencyWindow::default();
+        for sample in samples {
+            window.push(sample.map(|ms| (Duration::from_millis(ms), ProbeMethod::Icmp)));
+        }
+        window
+    }
+
+    #[test]
+    fn stats_count_loss_and_jitter_over_replies() {
+        let stats = window(&[Some(10), None, Some(30), Some(20)]).stats();
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.loss_pct, 25.0);
+        assert_eq!(stats.min_ms, Some(10.0));
+        assert_eq!(stats.max_ms, Some(30.0));
+        assert_eq!(stats.avg_ms, Some(20.0));
+        // |30 - 10| and |20 - 30|, the lost probe skipped
+        assert_eq!(stats.jitter_ms, Some(15.0));
+        assert_eq!(stats.method, Some(ProbeMethod::Icmp));
+    }
+
+    #[test]
+    fn all_lost_has_no_rtt() {
+        let stats = window(&[None, None]).stats();
+        assert_eq!(stats.loss_pct, 100.0);
+        assert_eq!(stats.avg_ms, None);
+        assert_eq!(stats.jitter_ms, None);
+
+        let empty = LatencyWindow::default().stats();
+        assert_eq!(empty.loss_pct, 0.0);
+        assert_eq!(empty.samples, 0);
+    }
+
+    #[test]
+    fn window_keeps_the_last_thirty_samples() {
+        let mut samples = vec![None; 10];
+        samples.extend(vec![Some(5); WINDOW_SIZE]);
+        let stats = window(&samples).stats();
+        assert_eq!(stats.samples, WINDOW_SIZE);
+        assert_eq!(stats.loss_pct, 0.0);
+    }
+
+    #[test]
+    fn label_falls_back_to_the_gateway() {
+        let stats = |ms| Some(window(&[Some(ms)]).stats());
+        let report = LatencyReport {
+            endpoint: stats(24),
+            gateway: stats(30),
+        };
+        assert_eq!(report.ping_label(), "24 ms");
+
+        let blocked = LatencyReport {
+            endpoint: None,
+            gateway: stats(30),
+        };
+        assert_eq!(blocked.ping_label(), "30 ms");
+        assert_eq!(LatencyReport::default().ping_label(), "—");
+    }
+}
@@ -0,0 +1,323 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Tunnel MTU discovery and per-profile overrides.
+//
+// Path MTU is found by binary-searching the largest ICMP echo that gets through
+// to the endpoint with Don't Fragment set, using the same native ICMP socket as
+// the latency probe. The tunnel MTU is that minus the WireGuard overhead for the
+// endpoint's address family. Results are cached per network and server; a
+// manual override for a profile (server key) always wins.
+//
+// Raw ICMP needs administrator rights, so with a helper paired it runs the
+// probe. A probe that gets no reply is remembered too, so later connects on
+// that network don't wait for it again; `discover_mtu` always probes afresh.
+
+use std::{net::IpAddr, time::Duration};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    append_log, error::NeraError, helper, latency, load_settings, servers, update_settings,
+};
+
+// IP header + 8 ICMP header on top of the echo payload
+const ICMP_OVERHEAD_V4: u16 = 20 + 8;
+const ICMP_OVERHEAD_V6: u16 = 40 + 8;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+// Outer IP + UDP (8) + WireGuard data header and tag (32)
+const WG_OVERHEAD_V4: u16 = 20 + 8 + 32;
+const WG_OVERHEAD_V6: u16 = 40 + 8 + 32;
+
+const MIN_PATH_MTU_V4: u16 = 576;
+// Every IPv6 link carries at least this much
+const MIN_PATH_MTU_V6: u16 = 1280;
+const MAX_PATH_MTU: u16 = 1500;
+// IPv6 inside the tunnel needs at least 1280
+const MIN_TUNNEL_MTU: u16 = 1280;
+// The smallest IPv4 path, less WireGuard's IPv4 overhead; IPv4 only below 1280
+const MIN_TUNNEL_MTU_V4: u16 = MIN_PATH_MTU_V4 - WG_OVERHEAD_V4;
+const DEFAULT_TUNNEL_MTU: u16 = 1420;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MtuReport {
+    pub server_key: String,
+    pub path_mtu: Option<u16>,
+    pub tunnel_mtu: u16,
+    pub source: MtuSource,
+    // Set when the MTU is too small for IPv6 inside the tunnel
+    pub warning: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MtuSource {
+    Override,
+    Discovered,
+    Default,
+}
+
+fn icmp_overhead(host: &IpAddr) -> u16 {
+    match host {
+        IpAddr::V4(_) => ICMP_OVERHEAD_V4,
+        IpAddr::V6(_) => ICMP_OVERHEAD_V6,
+    }
+}
+
+/// One DF-set echo of `payload` bytes.
+fn df_ping(host: &IpAddr, payload: u16) -> bool {
+    latency::icmp_echo_df(*host, payload as usize, PROBE_TIMEOUT).is_ok()
+}
+
+/// Largest IP packet that reaches `host` unfragmented, or `None` if even the
+/// minimum size gets no reply (ICMP filtered, host down, kill switch on...).
+pub fn probe_path_mtu(host: IpAddr) -> Option<u16> {
+    let overhead = icmp_overhead(&host);
+    let min_path = match host {
+        IpAddr::V4(_) => MIN_PATH_MTU_V4,
+        IpAddr::V6(_) => MIN_PATH_MTU_V6,
+    };
+    let mut low = min_path - overhead;
+    let mut high = MAX_PATH_MTU - overhead;
+
+    if !df_ping(&host, low) {
+        return None;
+    }
+    if df_ping(&host, high) {
+        return Some(MAX_PATH_MTU);
+    }
+
+    // Invariant: `low` gets through, `high` doesn't
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if df_ping(&host, mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some(low + overhead)
+}
+
+/// Probes from the helper if one is paired, since raw ICMP needs its rights.
+fn probe(host: IpAddr) -> Option<u16> {
+    match helper::call("probe_path_mtu", json!({ "host": host })) {
+        Some(reply) => reply
+            .ok()
+            .and_then(|mtu| mtu.as_u64())
+            .and_then(|mtu| u16::try_from(mtu).ok()),
+        None => probe_path_mtu(host),
+    }
+}
+
+fn ipv6_warning(tunnel_mtu: u16) -> Option<String> {
+    (tunnel_mtu < MIN_TUNNEL_MTU).then(|| {
+        format!("A tunnel MTU of {tunnel_mtu} only carries IPv4; IPv6 inside the tunnel needs {MIN_TUNNEL_MTU}.")
+    })
+}
+
+/// What WireGuard adds to every packet sent to `endpoint`.
+pub fn wg_overhead(endpoint: &IpAddr) -> u16 {
+    match endpoint {
+        IpAddr::V4(_) => WG_OVERHEAD_V4,
+        IpAddr::V6(_) => WG_OVERHEAD_V6,
+    }
+}
+
+/// Tunnel MTU that fits `path_mtu`. Never raised above what the path allows;
+/// below `MIN_TUNNEL_MTU` IPv6 can't run inside the tunnel, so that comes back
+/// as an error carrying the (still usable for IPv4) value.
+pub fn tunnel_mtu_for(path_mtu: u16, endpoint: &IpAddr) -> Result<u16, u16> {
+    let mtu = path_mtu.saturating_sub(wg_overhead(endpoint)).min(DEFAULT_TUNNEL_MTU);
+    if mtu < MIN_TUNNEL_MTU {
+        Err(mtu)
+    } else {
+        Ok(mtu)
+    }
+}
+
+fn cache_key(network_id: Option<&str>, server_key: &str) -> String {
+    format!("{}|{server_key}", network_id.unwrap_or("unknown"))
+}
+
+fn default_report(server: &servers::ServerInfo) -> MtuReport {
+    MtuReport {
+        server_key: server.key.to_string(),
+        path_mtu: None,
+        tunnel_mtu: DEFAULT_TUNNEL_MTU,
+        source: MtuSource::Default,
+        warning: None,
+    }
+}
+
+/// Probes the server's endpoint now and caches the result (or that there was
+/// none) for this network.
+pub fn discover(server: &servers::ServerInfo, network_id: Option<&str>) -> MtuReport {
+    let ip = server.socket_addr().map(|a| a.ip());
+    let path_mtu = ip.and_then(probe);
+
+    let report = match (path_mtu, ip) {
+        (Some(path), Some(ip)) => {
+            let tunnel_mtu = tunnel_mtu_for(path, &ip).unwrap_or_else(|mtu| mtu);
+            MtuReport {
+                server_key: server.key.to_string(),
+                path_mtu: Some(path),
+                tunnel_mtu,
+                source: MtuSource::Discovered,
+                warning: ipv6_warning(tunnel_mtu),
+            }
+        }
+        _ => default_report(server),
+    };
+    if let Some(warning) = &report.warning {
+        append_log(&format!("MTU discovery for {}: {warning}", server.key)).ok();
+    }
+
+    append_log(&format!(
+        "MTU discovery for {}: path {:?}, tunnel {}",
+        server.key, report.path_mtu, report.tunnel_mtu
+    ))
+    .ok();
+
+    let key = cache_key(network_id, server.key);
+    update_settings(|settings| {
+        if report.source == MtuSource::Discovered {
+            settings.mtu_probe_failed.remove(&key);
+            settings.discovered_mtu.insert(key, report.tunnel_mtu);
+        } else {
+            settings.discovered_mtu.remove(&key);
+            settings.mtu_probe_failed.insert(key);
+        }
+    });
+    report
+}
+
+/// MTU to write into the config: override, then cached discovery, then a
+/// fresh probe unless `probes_blocked` (the kill switch or a tunnel is up) or
+/// it already failed on this network.
+pub fn resolve(
+    server: &servers::ServerInfo,
+    network_id: Option<&str>,
+    probes_blocked: bool,
+) -> MtuReport {
+    let settings = load_settings();
+
+    if let Some(mtu) = settings.mtu_overrides.get(server.key) {
+        return MtuReport {
+            server_key: server.key.to_string(),
+            path_mtu: None,
+            tunnel_mtu: *mtu,
+            source: MtuSource::Override,
+            warning: ipv6_warning(*mtu),
+        };
+    }
+
+    if let Some(mtu) = settings.discovered_mtu.get(&cache_key(network_id, server.key)) {
+        return MtuReport {
+            server_key: server.key.to_string(),
+            path_mtu: None,
+            tunnel_mtu: *mtu,
+            source: MtuSource::Discovered,
+            warning: ipv6_warning(*mtu),
+        };
+    }
+
+    let key = cache_key(network_id, server.key);
+    if probes_blocked || settings.mtu_probe_failed.contains(&key) {
+        return default_report(server);
+    }
+    discover(server, network_id)
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub async fn discover_mtu(server_key: String) -> Result<MtuReport, NeraError> {
+    let server = servers::find_server(&server_key)
+        .ok_or_else(|| NeraError::NotFound(format!("Unknown server: {server_key}")))?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let network_id = crate::network_rules::current_network().id();
+        discover(server, network_id.as_deref())
+    })
+    .await
+    .map_err(|e| NeraError::Internal(format!("MTU discovery failed: {e}")))
+}
+
+#[tauri::command]
+pub fn get_mtu_override(server_key: String) -> Option<u16> {
+    load_settings().mtu_overrides.get(&server_key).copied()
+}
+
+/// Pins the tunnel MTU for one profile; `None` goes back to discovery. Below
+/// 1280 the tunnel is IPv4 only, which comes back as a warning.
+#[tauri::command]
+pub fn set_mtu_override(server_key: String, mtu: Option<u16>) -> Result<Option<String>, NeraError> {
+    if let Some(value) = mtu {
+        if !(MIN_TUNNEL_MTU_V4..=MAX_PATH_MTU).contains(&value) {
+            return Err(NeraError::InvalidInput(format!(
+                "MTU must be between {MIN_TUNNEL_MTU_V4} and {MAX_PATH_MTU}."
+            )));
+        }
+    }
+
+    update_settings(|settings| match mtu {
+        Some(value) => {
+            settings.mtu_overrides.insert(server_key.clone(), value);
+        }
+        None => {
+            settings.mtu_overrides.remove(&server_key);
+        }
+    });
+
+    append_log(&format!("MTU override for {server_key}: {mtu:?}")).ok();
+    let warning = mtu.and_then(ipv6_warning);
+    if let Some(warning) = &warning {
+        append_log(&format!("MTU override for {server_key}: {warning}")).ok();
+    }
+    Ok(warning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunnel_mtu_per_family() {
+        let v4: IpAddr = "203.0.113.7".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(tunnel_mtu_for(1500, &v4), Ok(1420));
+        assert_eq!(tunnel_mtu_for(1500, &v6), Ok(1420));
+        assert_eq!(tunnel_mtu_for(1400, &v4), Ok(1340));
+        assert_eq!(tunnel_mtu_for(1400, &v6), Ok(1320));
+    }
+
+    #[test]
+    fn small_path_is_not_raised() {
+        let v4: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(tunnel_mtu_for(1300, &v4), Err(1240));
+    }
+
+    #[test]
+    fn discovered_small_mtus_can_be_pinned() {
+        let v4: IpAddr = "203.0.113.7".parse().unwrap();
+        let discovered = tunnel_mtu_for(1300, &v4).unwrap_err();
+        assert!((MIN_TUNNEL_MTU_V4..MIN_TUNNEL_MTU).contains(&discovered));
+        assert!(ipv6_warning(discovered).is_some());
+        assert_eq!(ipv6_warning(MIN_TUNNEL_MTU), None);
+    }
+
+    #[test]
+    fn icmp_overhead_per_family() {
+        assert_eq!(icmp_overhead(&"203.0.113.7".parse().unwrap()), 28);
+        assert_eq!(icmp_overhead(&"2001:db8::1".parse().unwrap()), 48);
+    }
+}
@@ -0,0 +1,285 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Multi-hop (entry + exit) connections.
+//
+// Two chained WireGuard tunnels: the outer one only carries traffic for the exit
+// node's address to the entry node, the inner one carries everything else to the
+// exit node *through* the outer one. The inner tunnel pays WireGuard's overhead
+// for the exit node's address family a second time, so its MTU is reduced
+// accordingly.
+//
+// The inner tunnel has its own key and address, registered on first use, so
+// the exit node can't link the hop to the device key the entry node sees.
+
+use std::{collections::BTreeMap, net::IpAddr, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_client::{NeraApiClient, RegisterHopKeyRequest},
+    append_log,
+    error::NeraError,
+    generate_keypair, load_settings, log_dir, mtu, provisioning, psk, secrets,
+    servers::{self, ServerInfo},
+    update_settings, AppSettings,
+};
+
+const OUTER_TEMPLATE: &str = r#"[Interface]
+PrivateKey = {{PRIVATE_KEY}}
+Address = {{ADDRESS}}
+MTU = {{MTU}}
+
+[Peer]
+PublicKey = {{PEER_PUBLIC_KEY}}
+AllowedIPs = {{EXIT_ROUTE}}
+Endpoint = {{ENDPOINT}}
+PersistentKeepalive = 25
+"#;
+
+const INNER_TEMPLATE: &str = r#"[Interface]
+PrivateKey = {{PRIVATE_KEY}}
+Address = {{ADDRESS}}
+DNS = 1.1.1.1
+MTU = {{MTU}}
+
+[Peer]
+PublicKey = {{PEER_PUBLIC_KEY}}
+AllowedIPs = 0.0.0.0/0, ::/0
+Endpoint = {{ENDPOINT}}
+PersistentKeepalive = 25
+"#;
+
+/// Key and address of the inner tunnel, kept next to the device identity.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HopKey {
+    /// Sealed with `secrets`.
+    pub private_key: String,
+    pub public_key: String,
+    pub device_ip: String,
+    /// PSKs issued for the hop key, by server key; sealed like the device's.
+    #[serde(default)]
+    pub preshared_keys: BTreeMap<String, String>,
+}
+
+/// The hop key from settings, registering a new one with the API if there
+/// isn't one yet.
+pub fn ensure_hop_key(api: &NeraApiClient) -> Result<HopKey, String> {
+    let settings = load_settings();
+    if let Some(hop) = settings.hop_key {
+        return Ok(hop);
+    }
+    if settings.public_key.is_empty() {
+        return Err("Multi-hop requires a registered device identity.".to_string());
+    }
+
+    let (private_key, public_key) = generate_keypair();
+    let response = api
+        .register_hop_key(&RegisterHopKeyRequest {
+            device_public_key: settings.public_key.clone(),
+            hop_public_key: public_key.clone(),
+        })
+        .map_err(|e| format!("Registering the multi-hop key failed: {e}"))?;
+
+    let hop = HopKey {
+        private_key: secrets::seal(&private_key)?,
+        public_key,
+        device_ip: response.ip,
+        preshared_keys: psk::seal_all(&response.preshared_keys)?,
+    };
+    update_settings(|settings| settings.hop_key = Some(hop.clone()));
+    append_log("Multi-hop key registered.").ok();
+    Ok(hop)
+}
+
+/// Host route for the exit node, so only the inner tunnel's own packets go
+/// through the outer one.
+fn host_route(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => format!("{v4}/32"),
+        IpAddr::V6(v6) => format!("{v6}/128"),
+    }
+}
+
+/// Config for the inner (exit) tunnel. Its basename becomes the interface name.
+pub fn hop_conf_path() -> Result<PathBuf, String> {
+    log_dir().map(|mut dir| {
+        dir.push("nera-hop.conf");
+        dir
+    })
+}
+
+/// Renders `(outer, inner)` configs for an entry -> exit chain. `outer_mtu` is
+/// the MTU the path to the entry node allows for a single tunnel.
+pub fn render_configs(
+    settings: &AppSettings,
+    hop: &HopKey,
+    entry_key: &str,
+    exit_key: &str,
+    outer_mtu: u16,
+) -> Result<(String, String), String> {
+    if settings.private_key.is_empty() || settings.device_ip.is_empty() {
+        return Err("Multi-hop requires a registered device identity.".to_string());
+    }
+    if hop.device_ip.is_empty() {
+        return Err("The multi-hop key has no address assigned.".to_string());
+    }
+
+    let entry = servers::find_server(entry_key)
+        .ok_or_else(|| format!("Unknown entry server: {entry_key}"))?;
+    let exit =
+        servers::find_server(exit_key).ok_or_else(|| format!("Unknown exit server: {exit_key}"))?;
+    if entry.key == exit.key {
+        return Err("Entry and exit servers must be different.".to_string());
+    }
+    render(settings, hop, entry, exit, outer_mtu)
+}
+
+fn render(
+    settings: &AppSettings,
+    hop: &HopKey,
+    entry: &ServerInfo,
+    exit: &ServerInfo,
+    outer_mtu: u16,
+) -> Result<(String, String), String> {
+    let exit_ip = exit
+        .socket_addr()
+        .ok_or_else(|| format!("Could not resolve exit server {}", exit.host))?
+        .ip();
+
+    let outer = OUTER_TEMPLATE
+        .replace("{{PRIVATE_KEY}}", &provisioning::private_key(settings)?)
+        .replace("{{ADDRESS}}", &settings.device_ip)
+        .replace("{{MTU}}", &outer_mtu.to_string())
+        .replace("{{PEER_PUBLIC_KEY}}", entry.public_key)
+        .replace("{{EXIT_ROUTE}}", &host_route(exit_ip))
+        .replace("{{ENDPOINT}}", &entry.endpoint());
+    let outer = psk::apply(&outer, settings, entry.key)?;
+
+    let inner = INNER_TEMPLATE
+        .replace("{{PRIVATE_KEY}}", &secrets::open(&hop.private_key)?)
+        .replace("{{ADDRESS}}", &hop.device_ip)
+        // The inner tunnel's packets pay WireGuard's overhead again, to the exit
+        .replace(
+            "{{MTU}}",
+            &outer_mtu
+                .saturating_sub(mtu::wg_overhead(&exit_ip))
+                .to_string(),
+        )
+        .replace("{{PEER_PUBLIC_KEY}}", exit.public_key)
+        .replace("{{ENDPOINT}}", &exit.endpoint());
+    let inner = psk::apply_sealed(&inner, &hop.preshared_keys, exit.key)?;
+
+    Ok((outer, inner))
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_multi_hop_entry() -> Option<String> {
+    load_settings().multi_hop_entry
+}
+
+/// Sets (or with `None`, clears) the entry node. The selected server stays the exit.
+#[tauri::command]
+pub fn set_multi_hop_entry(entry_key: Option<String>) -> Result<(), NeraError> {
+    if let Some(key) = entry_key.as_deref() {
+        servers::find_server(key)
+            .ok_or_else(|| NeraError::NotFound(format!("Unknown entry server: {key}")))?;
+    }
+
+    append_log(&format!(
+        "Multi-hop entry set to {}",
+        entry_key.as_deref().unwrap_or("(off)")
+    ))
+    .ok();
+    update_settings(|settings| settings.multi_hop_entry = entry_key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(key: &'static str, host: &'static str, public_key: &'static str) -> ServerInfo {
+        ServerInfo {
+            key,
+            label: key,
+            host,
+            ports: &[51820],
+            public_key,
+            relay_port: None,
+        }
+    }
+
+    fn value<'a>(config: &'a str, key: &str) -> Option<&'a str> {
+        config.lines().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            Some(v.trim()).filter(|_| k.trim() == key)
+        })
+    }
+
+    fn render_to(exit_host: &'static str) -> (String, String) {
+        let (device_key, device_public) = generate_keypair();
+        let (hop_key, hop_public) = generate_keypair();
+        let settings = AppSettings {
+            private_key: secrets::seal(&device_key).unwrap(),
+            public_key: device_public,
+            device_ip: "10.8.0.2/32".to_string(),
+            ..Default::default()
+        };
+        let hop = HopKey {
+            private_key: secrets::seal(&hop_key).unwrap(),
+            public_key: hop_public,
+            device_ip: "10.9.0.7/32".to_string(),
+            preshared_keys: BTreeMap::new(),
+        };
+        let entry = server("entry", "198.51.100.1", "ENTRYKEY=");
+        let exit = server("exit", exit_host, "EXITKEY=");
+
+        let (outer, inner) = render(&settings, &hop, &entry, &exit, 1420).unwrap();
+        assert_eq!(value(&outer, "PrivateKey"), Some(device_key.as_str()));
+        assert_eq!(value(&inner, "PrivateKey"), Some(hop_key.as_str()));
+        (outer, inner)
+    }
+
+    #[test]
+    fn outer_tunnel_only_routes_the_exit_node() {
+        let (outer, inner) = render_to("203.0.113.7");
+        assert_eq!(value(&outer, "AllowedIPs"), Some("203.0.113.7/32"));
+        assert_eq!(value(&outer, "Endpoint"), Some("198.51.100.1:51820"));
+        assert_eq!(value(&outer, "PublicKey"), Some("ENTRYKEY="));
+        assert_eq!(value(&outer, "Address"), Some("10.8.0.2/32"));
+
+        assert_eq!(value(&inner, "AllowedIPs"), Some("0.0.0.0/0, ::/0"));
+        assert_eq!(value(&inner, "Endpoint"), Some("203.0.113.7:51820"));
+        assert_eq!(value(&inner, "PublicKey"), Some("EXITKEY="));
+        assert_eq!(value(&inner, "Address"), Some("10.9.0.7/32"));
+    }
+
+    #[test]
+    fn inner_mtu_pays_the_exit_family_overhead() {
+        let (outer, inner) = render_to("203.0.113.7");
+        assert_eq!(value(&outer, "MTU"), Some("1420"));
+        assert_eq!(value(&inner, "MTU"), Some("1360"));
+
+        let (_, inner) = render_to("2001:db8::7");
+        assert_eq!(value(&inner, "MTU"), Some("1340"));
+    }
+
+    #[test]
+    fn host_route_per_family() {
+        assert_eq!(host_route("203.0.113.7".parse().unwrap()), "203.0.113.7/32");
+        assert_eq!(
+            host_route("2001:db8::1".parse().unwrap()),
+            "2001:db8::1/128"
+        );
+    }
+}
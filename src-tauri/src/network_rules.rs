@@ -0,0 +1,447 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Trusted network / auto-connect rules.
+//
+// Rules are persisted in AppSettings and evaluated in order: the first rule that
+// matches the current physical network decides what happens. They run once on
+// startup and again every time the network watcher sees the network change.
+
+use std::{thread, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    append_log, connect_vpn_locked, disconnect_vpn_locked, error::NeraError, events,
+    hidden_command, load_settings, session_journal::DisconnectReason, update_settings, VpnState,
+};
+
+// Each poll runs PowerShell and netsh, so keep it infrequent
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Connect,
+    Disconnect,
+    Ignore,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleMatchKind {
+    InterfaceName,
+    GatewayMac,
+    Ssid,
+    DnsSuffix,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetworkRule {
+    pub id: String,
+    pub kind: RuleMatchKind,
+    pub value: String,
+    pub action: RuleAction,
+}
+
+/// What we know about the physical network we're currently attached to.
+/// Any field can be missing (e.g. no SSID on Ethernet).
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NetworkSnapshot {
+    pub interface_name: Option<String>,
+    pub gateway_mac: Option<String>,
+    pub ssid: Option<String>,
+    pub dns_suffix: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct NetworkChangedPayload {
+    network: NetworkSnapshot,
+    matched_rule: Option<NetworkRule>,
+}
+
+#[derive(Deserialize)]
+struct RouteInfo {
+    interface: Option<String>,
+    gateway_mac: Option<String>,
+    dns_suffix: Option<String>,
+}
+
+// Default route on the physical side (our own tunnel is excluded), plus the
+// gateway's MAC from the neighbour table and the interface's DNS suffix.
+const ROUTE_INFO_SCRIPT: &str = r#"
+$r = Get-NetRoute -DestinationPrefix '0.0.0.0/0' -ErrorAction SilentlyContinue |
+    Where-Object { $_.InterfaceAlias -notlike 'nera*' } |
+    Sort-Object RouteMetric | Select-Object -First 1
+if ($r) {
+    $n = Get-NetNeighbor -IPAddress $r.NextHop -ErrorAction SilentlyContinue | Select-Object -First 1
+    $d = Get-DnsClient -InterfaceIndex $r.ifIndex -ErrorAction SilentlyContinue
+    [pscustomobject]@{
+        interface = $r.InterfaceAlias
+        gateway_mac = $n.LinkLayerAddress
+        dns_suffix = $d.ConnectionSpecificSuffix
+    } | ConvertTo-Json -Compress
+}
+"#;
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_lowercase().replace('-', ":")
+}
+
+fn read_ssid(interface: &str) -> Option<String> {
+    let output = hidden_command("netsh")
+        .args(["wlan", "show", "interfaces"])
+        .output()
+        .ok()?;
+    parse_ssid(&String::from_utf8_lossy(&output.stdout), interface)
+}
+
+/// Pulls the SSID for `interface` out of `netsh wlan show interfaces`.
+fn parse_ssid(stdout: &str, interface: &str) -> Option<String> {
+    let mut current_name: Option<String> = None;
+    for line in stdout.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        let key = key.trim();
+        let value = value.trim();
+
+        if key == "Name" {
+            current_name = Some(value.to_string());
+        } else if key == "SSID" {
+            let matches = current_name
+                .as_deref()
+                .map(|n| n.eq_ignore_ascii_case(interface))
+                .unwrap_or(false);
+            if matches && !value.is_empty() {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+pub fn current_network() -> NetworkSnapshot {
+    let output = hidden_command("powershell")
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            ROUTE_INFO_SCRIPT,
+        ])
+        .output();
+
+    let route: Option<RouteInfo> = match output {
+        Ok(o) if o.status.success() => serde_json::from_slice(&o.stdout).ok(),
+        _ => None,
+    };
+
+    let route = match route {
+        Some(r) => r,
+        None => return NetworkSnapshot::default(),
+    };
+
+    let interface_name = non_empty(route.interface);
+    let ssid = interface_name.as_deref().and_then(read_ssid);
+
+    NetworkSnapshot {
+        interface_name,
+        gateway_mac: non_empty(route.gateway_mac).map(|m| normalize_mac(&m)),
+        ssid,
+        dns_suffix: non_empty(route.dns_suffix).map(|s| s.to_lowercase()),
+    }
+}
+
+impl NetworkSnapshot {
+    /// Stable-ish identifier for "this network": the gateway's MAC when we know
+    /// it, otherwise the SSID or interface name.
+    pub fn id(&self) -> Option<String> {
+        self.gateway_mac
+            .as_ref()
+            .map(|m| format!("mac:{m}"))
+            .or_else(|| self.ssid.as_ref().map(|s| format!("ssid:{s}")))
+            .or_else(|| self.interface_name.as_ref().map(|i| format!("if:{i}")))
+    }
+}
+
+impl NetworkRule {
+    fn matches(&self, network: &NetworkSnapshot) -> bool {
+        let value = self.value.trim();
+        match self.kind {
+            RuleMatchKind::InterfaceName => network
+                .interface_name
+                .as_deref()
+                .map(|n| n.eq_ignore_ascii_case(value))
+                .unwrap_or(false),
+            RuleMatchKind::GatewayMac => network
+                .gateway_mac
+                .as_deref()
+                .map(|m| m == normalize_mac(value))
+                .unwrap_or(false),
+            RuleMatchKind::Ssid => network.ssid.as_deref() == Some(value),
+            RuleMatchKind::DnsSuffix => {
+                let rule = value.trim_start_matches('.').to_lowercase();
+                network
+                    .dns_suffix
+                    .as_deref()
+                    .map(|s| s == rule || s.ends_with(&format!(".{rule}")))
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
+pub fn matching_rule(rules: &[NetworkRule], network: &NetworkSnapshot) -> Option<NetworkRule> {
+    rules.iter().find(|r| r.matches(network)).cloned()
+}
+
+/// Applies the first matching rule to the current network.
+fn apply_rules(app: &AppHandle, network: &NetworkSnapshot) {
+    let settings = load_settings();
+    let rule = matching_rule(&settings.network_rules, network);
+
+    let _ = events::emit(
+        app,
+        "network-changed",
+        NetworkChangedPayload {
+            network: network.clone(),
+            matched_rule: rule.clone(),
+        },
+    );
+
+    let rule = match rule {
+        Some(r) => r,
+        None => {
+            append_log("Network rules: no rule matched, leaving connection as-is.").ok();
+            return;
+        }
+    };
+
+    let state = app.state::<VpnState>();
+    // Decide under the lock, so a connect or disconnect already under way
+    // finishes first instead of racing this one
+    let _transition = state.transition_lock.lock().unwrap();
+    let connected = *state.connected.lock().unwrap();
+
+    match rule.action {
+        RuleAction::Connect if !connected => {
+            append_log(&format!(
+                "Network rules: rule {} matched, auto-connecting.",
+                rule.id
+            ))
+            .ok();
+            if let Err(e) = connect_vpn_locked(Some(app), &state, Some(settings.selected_server)) {
+                append_log(&format!("Network rules: auto-connect failed: {e}")).ok();
+            }
+        }
+        RuleAction::Disconnect if connected => {
+            append_log(&format!(
+                "Network rules: rule {} matched, disconnecting.",
+                rule.id
+            ))
+            .ok();
+            if let Err(e) = disconnect_vpn_locked(Some(app), &state, DisconnectReason::Network) {
+                append_log(&format!("Network rules: auto-disconnect failed: {e}")).ok();
+            }
+        }
+        _ => {
+            append_log(&format!(
+                "Network rules: rule {} matched, nothing to do.",
+                rule.id
+            ))
+            .ok();
+        }
+    }
+}
+
+/// Polls the physical network and re-evaluates the rules whenever it changes.
+/// The first iteration always evaluates, which covers the startup case.
+pub fn spawn_network_watcher(app: AppHandle) {
+    thread::spawn(move || {
+        let mut last: Option<NetworkSnapshot> = None;
+
+        loop {
+            let network = current_network();
+
+            if last.as_ref() != Some(&network) {
+                append_log(&format!(
+                    "Network change detected: interface={} ssid={} dns_suffix={}",
+                    network.interface_name.as_deref().unwrap_or("-"),
+                    network.ssid.as_deref().unwrap_or("-"),
+                    network.dns_suffix.as_deref().unwrap_or("-"),
+                ))
+                .ok();
+                apply_rules(&app, &network);
+                last = Some(network);
+            }
+
+            thread::sleep(WATCH_INTERVAL);
+        }
+    });
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_network_rules() -> Vec<NetworkRule> {
+    load_settings().network_rules
+}
+
+#[tauri::command]
+pub fn add_network_rule(
+    kind: RuleMatchKind,
+    value: String,
+    action: RuleAction,
+) -> Result<NetworkRule, NeraError> {
+    if value.trim().is_empty() {
+        return Err(NeraError::InvalidInput(
+            "Rule value cannot be empty.".to_string(),
+        ));
+    }
+
+    let rule = NetworkRule {
+        id: format!("{:016x}", rand::random::<u64>()),
+        kind,
+        value: value.trim().to_string(),
+        action,
+    };
+
+    update_settings(|settings| settings.network_rules.push(rule.clone()));
+
+    append_log(&format!(
+        "Network rule added: {} {:?} {:?}",
+        rule.id, rule.kind, rule.action
+    ))
+    .ok();
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn remove_network_rule(id: String) -> Result<(), NeraError> {
+    let removed = update_settings(|settings| {
+        let before = settings.network_rules.len();
+        settings.network_rules.retain(|r| r.id != id);
+        settings.network_rules.len() != before
+    });
+    if !removed {
+        return Err(NeraError::NotFound(format!("No network rule with id {id}")));
+    }
+
+    append_log(&format!("Network rule removed: {id}")).ok();
+    Ok(())
+}
+
+/// Replaces the whole list, which is how the UI reorders rule priority.
+#[tauri::command]
+pub fn set_network_rules(rules: Vec<NetworkRule>) -> Result<(), NeraError> {
+    update_settings(|settings| settings.network_rules = rules);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_current_network() -> NetworkSnapshot {
+    current_network()
+}
+
+#[tauri::command]
+pub fn evaluate_network_rules(app: AppHandle) -> NetworkSnapshot {
+    let network = current_network();
+    apply_rules(&app, &network);
+    network
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(kind: RuleMatchKind, value: &str) -> NetworkRule {
+        NetworkRule {
+            id: "r".to_string(),
+            kind,
+            value: value.to_string(),
+            action: RuleAction::Connect,
+        }
+    }
+
+    fn network() -> NetworkSnapshot {
+        NetworkSnapshot {
+            interface_name: Some("Wi-Fi".to_string()),
+            gateway_mac: Some("aa:bb:cc:dd:ee:ff".to_string()),
+            ssid: Some("Home".to_string()),
+            dns_suffix: Some("corp.example.com".to_string()),
+        }
+    }
+
+    #[test]
+    fn normalize_mac_lowercases_and_uses_colons() {
+        assert_eq!(normalize_mac(" AA-BB-CC-DD-EE-FF "), "aa:bb:cc:dd:ee:ff");
+        assert_eq!(normalize_mac("aa:bb:cc:dd:ee:ff"), "aa:bb:cc:dd:ee:ff");
+    }
+
+    #[test]
+    fn rules_match_each_kind() {
+        let net = network();
+        assert!(rule(RuleMatchKind::InterfaceName, "wi-fi").matches(&net));
+        assert!(rule(RuleMatchKind::GatewayMac, "AA-BB-CC-DD-EE-FF").matches(&net));
+        assert!(rule(RuleMatchKind::Ssid, "Home").matches(&net));
+        assert!(!rule(RuleMatchKind::Ssid, "home").matches(&net));
+        assert!(rule(RuleMatchKind::DnsSuffix, ".example.com").matches(&net));
+        assert!(rule(RuleMatchKind::DnsSuffix, "corp.example.com").matches(&net));
+        assert!(!rule(RuleMatchKind::DnsSuffix, "ample.com").matches(&net));
+    }
+
+    #[test]
+    fn missing_fields_never_match() {
+        let net = NetworkSnapshot::default();
+        assert!(!rule(RuleMatchKind::InterfaceName, "Wi-Fi").matches(&net));
+        assert!(!rule(RuleMatchKind::GatewayMac, "aa:bb:cc:dd:ee:ff").matches(&net));
+        assert!(!rule(RuleMatchKind::Ssid, "Home").matches(&net));
+        assert!(!rule(RuleMatchKind::DnsSuffix, "example.com").matches(&net));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = vec![
+            rule(RuleMatchKind::Ssid, "Office"),
+            rule(RuleMatchKind::Ssid, "Home"),
+            rule(RuleMatchKind::InterfaceName, "Wi-Fi"),
+        ];
+        let matched = matching_rule(&rules, &network()).unwrap();
+        assert_eq!(matched.kind, RuleMatchKind::Ssid);
+        assert_eq!(matched.value, "Home");
+    }
+
+    #[test]
+    fn parse_ssid_picks_the_named_interface() {
+        let output = "
+There are 2 interfaces on the system:
+
+    Name                   : Ethernet 2
+    State                  : disconnected
+
+    Name                   : Wi-Fi
+    Description            : Intel(R) Wi-Fi 6 AX201 160MHz
+    State                  : connected
+    SSID                   : Cafe: Guest
+    BSSID                  : 11:22:33:44:55:66
+";
+        assert_eq!(parse_ssid(output, "wi-fi").as_deref(), Some("Cafe: Guest"));
+        assert_eq!(parse_ssid(output, "Ethernet 2"), None);
+        assert_eq!(parse_ssid("", "Wi-Fi"), None);
+    }
+}
@@ -0,0 +1,179 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// SPKI pinning for the account API's TLS connections.
+//
+// The API's certificate is accepted only if the SHA-256 of its
+// SubjectPublicKeyInfo matches one of the pins compiled into the build
+// (`NERA_API_PIN_PRIMARY`, `NERA_API_PIN_BACKUP`; base64, optionally prefixed
+// with `sha256/`). Name and chain checks are skipped: the pin is the trust
+// anchor, which is what lets the API live on a bare IP with its own cert.
+// A build without pins refuses every API connection; release builds fail in
+// build.rs instead of shipping that way.
+
+use std::{sync::Arc, time::SystemTime};
+
+use base64::{engine::general_purpose, Engine as _};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, Error, ServerName,
+};
+use sha2::{Digest, Sha256};
+
+use crate::append_log;
+
+/// Marker in the TLS error so a pin failure can be told apart from other
+/// connection errors once it comes back wrapped by reqwest.
+pub const PIN_MISMATCH: &str = "NERA_PIN_MISMATCH";
+
+const BUILD_PINS: [Option<&str>; 2] = [
+    option_env!("NERA_API_PIN_PRIMARY"),
+    option_env!("NERA_API_PIN_BACKUP"),
+];
+
+pub fn decode_pin(pin: &str) -> Option<[u8; 32]> {
+    let pin = pin.trim();
+    let pin = pin.strip_prefix("sha256/").unwrap_or(pin);
+    let bytes = general_purpose::STANDARD.decode(pin).ok()?;
+    <[u8; 32]>::try_from(bytes.as_slice()).ok()
+}
+
+pub fn build_pins() -> Vec<[u8; 32]> {
+    BUILD_PINS
+        .iter()
+        .flatten()
+        .filter_map(|pin| {
+            let decoded = decode_pin(pin);
+            if decoded.is_none() {
+                append_log(&format!("Ignoring malformed API pin: {pin}")).ok();
+            }
+            decoded
+        })
+        .collect()
+}
+
+/// Tag, offset of the contents and their length for the DER element at `pos`.
+fn der_element(der: &[u8], pos: usize) -> Option<(u8, usize, usize)> {
+    let tag = *der.get(pos)?;
+    let first = *der.get(pos + 1)? as usize;
+    if first < 0x80 {
+        return Some((tag, pos + 2, first));
+    }
+
+    let len_bytes = first & 0x7F;
+    if len_bytes == 0 || len_bytes > 4 {
+        return None;
+    }
+    let mut len = 0usize;
+    for i in 0..len_bytes {
+        len = (len << 8) | *der.get(pos + 2 + i)? as usize;
+    }
+    Some((tag, pos + 2 + len_bytes, len))
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of an X.509 certificate.
+pub fn spki_der(cert: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION_TAG: u8 = 0xA0;
+
+    // Certificate ::= SEQUENCE { tbsCertificate, ... }
+    let (tag, cert_body, _) = der_element(cert, 0)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, mut pos, _) = der_element(cert, cert_body)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    // tbsCertificate: [0] version (optional), serialNumber, signature,
+    // issuer, validity, subject, subjectPublicKeyInfo
+    let (tag, start, len) = der_element(cert, pos)?;
+    if tag == VERSION_TAG {
+        pos = start + len;
+    }
+    for _ in 0..5 {
+        let (_, start, len) = der_element(cert, pos)?;
+        pos = start + len;
+    }
+
+    let (tag, start, len) = der_element(cert, pos)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    cert.get(pos..start + len)
+}
+
+pub fn spki_sha256(cert: &[u8]) -> Option<[u8; 32]> {
+    spki_der(cert).map(|spki| Sha256::digest(spki).into())
+}
+
+struct SpkiPinVerifier {
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for SpkiPinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if self.pins.is_empty() {
+            return Err(Error::General(format!(
+                "{PIN_MISMATCH}: this build has no API pins"
+            )));
+        }
+        let hash = spki_sha256(&end_entity.0).ok_or_else(|| {
+            Error::General(format!("{PIN_MISMATCH}: unreadable server certificate"))
+        })?;
+
+        if self.pins.contains(&hash) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::General(format!(
+                "{PIN_MISMATCH}: server key sha256/{} is not pinned",
+                general_purpose::STANDARD.encode(hash)
+            )))
+        }
+    }
+}
+
+/// TLS config for the API, pinned to the build's pins.
+pub fn api_tls_config() -> ClientConfig {
+    let pins = build_pins();
+    if pins.is_empty() {
+        append_log("No API pins in this build; API connections will be refused.").ok();
+    }
+    pinned_tls_config(pins)
+}
+
+/// TLS config accepting only servers whose key is in `pins`.
+pub fn pinned_tls_config(pins: Vec<[u8; 32]>) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(SpkiPinVerifier { pins }))
+        .with_no_client_auth()
+}
+
+/// Whether `error` (or anything it wraps) is a pin failure.
+pub fn is_pin_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if e.to_string().contains(PIN_MISMATCH) {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
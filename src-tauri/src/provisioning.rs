@@ -0,0 +1,297 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Device provisioning.
+//
+// An install only connects with its own registered key:
+//   unprovisioned - no keypair yet
+//   key_generated - keypair saved locally, the server doesn't know it
+//   registered    - sign-up/sign-in accepted the key and assigned an address
+//   ready         - the frontend finished sign-in; connecting is allowed
+// The state is persisted, but always checked against the keys and address in
+// settings so a stale value can never claim more than the settings back up.
+
+use std::fmt;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_client::AuthResponse, append_log, error::NeraError, generate_keypair, load_settings, psk,
+    secrets, update_settings, AppSettings,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvisioningState {
+    Unprovisioned,
+    KeyGenerated,
+    Registered,
+    Ready,
+}
+
+impl Default for ProvisioningState {
+    fn default() -> Self {
+        ProvisioningState::Unprovisioned
+    }
+}
+
+/// Returned when something needs a ready device and this one isn't.
+#[derive(Clone, Debug, Serialize)]
+pub struct NotProvisioned {
+    pub state: ProvisioningState,
+}
+
+impl fmt::Display for NotProvisioned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hint = match self.state {
+            ProvisioningState::Unprovisioned => "no device key has been generated",
+            ProvisioningState::KeyGenerated => "sign in or create an account first",
+            ProvisioningState::Registered => "finish signing in first",
+            ProvisioningState::Ready => "ready",
+        };
+        write!(f, "This device isn't set up to connect yet: {hint}.")
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProvisioningStatus {
+    pub state: ProvisioningState,
+    pub public_key: Option<String>,
+    pub device_ip: Option<String>,
+}
+
+/// The stored state, capped by what settings actually hold.
+pub fn current(settings: &AppSettings) -> ProvisioningState {
+    if settings.private_key.is_empty() || settings.public_key.is_empty() {
+        return ProvisioningState::Unprovisioned;
+    }
+    let has_address = !settings.device_ip.is_empty();
+
+    match settings.provisioning {
+        // Installs from before the state was tracked
+        ProvisioningState::Unprovisioned if has_address => ProvisioningState::Ready,
+        ProvisioningState::Unprovisioned => ProvisioningState::KeyGenerated,
+        ProvisioningState::Registered | ProvisioningState::Ready if !has_address => {
+            ProvisioningState::KeyGenerated
+        }
+        state => state,
+    }
+}
+
+pub fn require_ready(settings: &AppSettings) -> Result<(), NotProvisioned> {
+    match current(settings) {
+        ProvisioningState::Ready => Ok(()),
+        state => Err(NotProvisioned { state }),
+    }
+}
+
+fn set_state(settings: &mut AppSettings, state: ProvisioningState) {
+    if settings.provisioning != state {
+        append_log(&format!(
+            "Provisioning: {:?} -> {state:?}",
+            current(settings)
+        ))
+        .ok();
+    }
+    settings.provisioning = state;
+}
+
+/// Replaces any identity in `settings` with a fresh, unregistered keypair.
+/// The private key is stored sealed.
+pub fn generate_key(settings: &mut AppSettings) -> Result<(), String> {
+    let (private_key, public_key) = generate_keypair();
+    settings.private_key = secrets::seal(&private_key)?;
+    settings.public_key = public_key;
+    settings.device_ip = String::new();
+    settings.key_created_at = Some(chrono::Local::now().to_rfc3339());
+    settings.pending_rotation = None;
+    settings.hop_key = None;
+    psk::clear(settings);
+    set_state(settings, ProvisioningState::KeyGenerated);
+    Ok(())
+}
+
+/// The device's private key, opened from settings.
+pub fn private_key(settings: &AppSettings) -> Result<String, String> {
+    secrets::open(&settings.private_key).map_err(|e| format!("Could not read the device key: {e}"))
+}
+
+// A WireGuard key as versions before sealing stored it
+fn is_plain_key(value: &str) -> bool {
+    general_purpose::STANDARD
+        .decode(value.trim())
+        .map_or(false, |bytes| bytes.len() == 32)
+}
+
+/// Seals private keys earlier versions left in plain text: the active one,
+/// a pending rotation's and those of stashed identities.
+pub fn seal_plain_keys() {
+    let result = update_settings(|settings| {
+        let mut keys: Vec<&mut String> = vec![&mut settings.private_key];
+        keys.extend(
+            settings
+                .pending_rotation
+                .as_mut()
+                .map(|p| &mut p.new_private_key),
+        );
+        for identity in &mut settings.identities {
+            keys.push(&mut identity.private_key);
+            keys.extend(
+                identity
+                    .pending_rotation
+                    .as_mut()
+                    .map(|p| &mut p.new_private_key),
+            );
+        }
+        for key in keys.into_iter().filter(|k| is_plain_key(k)) {
+            let sealed = secrets::seal(key)?;
+            *key = sealed;
+        }
+        Ok::<_, String>(())
+    });
+    if let Err(e) = result {
+        append_log(&format!("Could not seal stored private keys: {e}")).ok();
+    }
+}
+
+/// The server accepted `public_key`, assigned an address and issued PSKs.
+pub fn mark_registered(public_key: &str, response: &AuthResponse) -> Result<(), String> {
+    update_settings(|settings| {
+        if settings.public_key != public_key {
+            return Err("The registered key is no longer this device's key.".to_string());
+        }
+        settings.device_ip = response.ip.clone();
+        psk::replace(settings, &response.preshared_keys)?;
+        if current(settings) < ProvisioningState::Registered {
+            set_state(settings, ProvisioningState::Registered);
+        }
+        Ok(())
+    })
+}
+
+/// Last step of sign-in. Needs a registered key with an address.
+pub fn mark_ready(settings: &mut AppSettings) -> Result<(), NotProvisioned> {
+    match current(settings) {
+        state @ (ProvisioningState::Unprovisioned | ProvisioningState::KeyGenerated) => {
+            Err(NotProvisioned { state })
+        }
+        _ => {
+            set_state(settings, ProvisioningState::Ready);
+            Ok(())
+        }
+    }
+}
+
+pub fn reset(settings: &mut AppSettings) {
+    set_state(settings, ProvisioningState::Unprovisioned);
+}
+
+fn status(settings: &AppSettings) -> ProvisioningStatus {
+    let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+    ProvisioningStatus {
+        state: current(settings),
+        public_key: non_empty(&settings.public_key),
+        device_ip: non_empty(&settings.device_ip),
+    }
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_provisioning_state() -> ProvisioningStatus {
+    status(&load_settings())
+}
+
+/// Takes the next step that can be done locally. Registering a key needs
+/// credentials, so that one goes through `register_account`/`login_account`.
+#[tauri::command]
+pub fn advance_provisioning() -> Result<ProvisioningStatus, NeraError> {
+    update_settings(|settings| {
+        match current(settings) {
+            ProvisioningState::Unprovisioned => generate_key(settings)?,
+            ProvisioningState::KeyGenerated => {
+                return Err(NeraError::NotProvisioned(NotProvisioned {
+                    state: ProvisioningState::KeyGenerated,
+                }))
+            }
+            ProvisioningState::Registered => mark_ready(settings)?,
+            ProvisioningState::Ready => {}
+        }
+        Ok(status(settings))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bare_wireguard_keys_count_as_plain() {
+        let (private_key, _) = generate_keypair();
+        assert!(is_plain_key(&private_key));
+        assert!(!is_plain_key(""));
+        // A sealed key is the nonce, the key and the tag
+        let sealed = general_purpose::STANDARD.encode([0u8; 12 + 44 + 16]);
+        assert!(!is_plain_key(&sealed));
+    }
+
+    fn with_key(stored: ProvisioningState, device_ip: &str) -> AppSettings {
+        AppSettings {
+            private_key: "sealed".to_string(),
+            public_key: "public".to_string(),
+            device_ip: device_ip.to_string(),
+            provisioning: stored,
+            ..AppSettings::default()
+        }
+    }
+
+    #[test]
+    fn current_is_capped_by_what_settings_hold() {
+        use ProvisioningState::*;
+
+        // No keypair means nothing else counts
+        let mut settings = with_key(Ready, "10.0.0.2/32");
+        settings.private_key.clear();
+        assert_eq!(current(&settings), Unprovisioned);
+
+        // Installs from before the state was stored
+        assert_eq!(current(&with_key(Unprovisioned, "10.0.0.2/32")), Ready);
+        assert_eq!(current(&with_key(Unprovisioned, "")), KeyGenerated);
+
+        // A lost address sends a registered key back a step
+        assert_eq!(current(&with_key(Registered, "")), KeyGenerated);
+        assert_eq!(current(&with_key(Ready, "")), KeyGenerated);
+
+        assert_eq!(
+            current(&with_key(KeyGenerated, "10.0.0.2/32")),
+            KeyGenerated
+        );
+        assert_eq!(current(&with_key(Registered, "10.0.0.2/32")), Registered);
+        assert_eq!(current(&with_key(Ready, "10.0.0.2/32")), Ready);
+    }
+
+    #[test]
+    fn only_a_registered_key_can_be_marked_ready() {
+        let mut settings = with_key(ProvisioningState::KeyGenerated, "10.0.0.2/32");
+        let refused = mark_ready(&mut settings).unwrap_err();
+        assert_eq!(refused.state, ProvisioningState::KeyGenerated);
+        assert_eq!(settings.provisioning, ProvisioningState::KeyGenerated);
+
+        let mut settings = AppSettings::default();
+        let refused = mark_ready(&mut settings).unwrap_err();
+        assert_eq!(refused.state, ProvisioningState::Unprovisioned);
+
+        let mut settings = with_key(ProvisioningState::Registered, "10.0.0.2/32");
+        mark_ready(&mut settings).unwrap();
+        assert_eq!(current(&settings), ProvisioningState::Ready);
+        assert!(require_ready(&settings).is_ok());
+    }
+}
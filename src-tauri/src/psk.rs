@@ -0,0 +1,324 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// WireGuard preshared keys.
+//
+// The API issues one PSK per server for the device's key, at registration and
+// with every key rotation. Mixed into the handshake, it keeps recorded traffic
+// safe even if Curve25519 alone is broken later (e.g. by a quantum computer).
+// PSKs are sealed with `secrets` in settings and only opened to render a
+// config. They can be rotated on their own schedule, separate from the key.
+
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    append_log, error::NeraError, load_settings, provisioning, secrets, servers, traffic,
+    update_settings, AppSettings, VpnState,
+};
+
+const MAX_INTERVAL_DAYS: u32 = 365;
+
+#[derive(Serialize)]
+pub struct PskStatus {
+    /// Servers this device has a preshared key for.
+    pub servers: Vec<String>,
+    pub rotated_at: Option<String>,
+    pub interval_days: Option<u32>,
+    pub next_rotation_at: Option<String>,
+}
+
+fn is_valid(psk: &str) -> bool {
+    general_purpose::STANDARD
+        .decode(psk.trim())
+        .map_or(false, |bytes| bytes.len() == 32)
+}
+
+/// Seals PSKs from the API for storage, dropping any that aren't valid keys.
+pub fn seal_all(issued: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>, String> {
+    let mut sealed = BTreeMap::new();
+    for (server, psk) in issued {
+        if !is_valid(psk) {
+            append_log(&format!("Ignoring malformed preshared key for {server}")).ok();
+            continue;
+        }
+        sealed.insert(server.clone(), secrets::seal(psk.trim())?);
+    }
+    Ok(sealed)
+}
+
+/// Replaces the stored PSKs with freshly issued ones.
+pub fn replace(
+    settings: &mut AppSettings,
+    issued: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    settings.preshared_keys = seal_all(issued)?;
+    settings.psk_rotated_at = if settings.preshared_keys.is_empty() {
+        None
+    } else {
+        Some(chrono::Local::now().to_rfc3339())
+    };
+    Ok(())
+}
+
+pub fn clear(settings: &mut AppSettings) {
+    settings.preshared_keys.clear();
+    settings.psk_rotated_at = None;
+}
+
+fn open_for(sealed: &BTreeMap<String, String>, server_key: &str) -> Result<Option<String>, String> {
+    match sealed.get(server_key) {
+        Some(s) => secrets::open(s)
+            .map(Some)
+            .map_err(|e| format!("Preshared key for {server_key}: {e}")),
+        None => Ok(None),
+    }
+}
+
+/// Puts `psk` right after the peer's `PublicKey`, replacing any old one.
+fn set_peer_psk(config: &str, psk: Option<&str>) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut in_peer = false;
+    for line in config.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_peer = trimmed.eq_ignore_ascii_case("[Peer]");
+        }
+        let key = trimmed
+            .split_once('=')
+            .map(|(k, _)| k.trim().to_ascii_lowercase());
+
+        if in_peer && key.as_deref() == Some("presharedkey") {
+            continue;
+        }
+        out.push(line.to_string());
+        if in_peer && key.as_deref() == Some("publickey") {
+            if let Some(psk) = psk {
+                out.push(format!("PresharedKey = {psk}"));
+            }
+        }
+    }
+    out.push(String::new());
+    out.join("\n")
+}
+
+/// Adds the PSK for `server_key` to a rendered config, if there is one.
+pub fn apply(config: &str, settings: &AppSettings, server_key: &str) -> Result<String, String> {
+    apply_sealed(config, &settings.preshared_keys, server_key)
+}
+
+/// Like `apply`, with PSKs from another key (e.g. the multi-hop key).
+pub fn apply_sealed(
+    config: &str,
+    sealed: &BTreeMap<String, String>,
+    server_key: &str,
+) -> Result<String, String> {
+    let psk = open_for(sealed, server_key)?;
+    Ok(set_peer_psk(config, psk.as_deref()))
+}
+
+/// Sets the PSKs in `sealed` on running interfaces, matched by peer key. Pass
+/// only interfaces using the key the PSKs belong to.
+pub fn apply_live(interfaces: &[String], sealed: &BTreeMap<String, String>) -> Result<(), String> {
+    for interface in interfaces {
+        for peer in traffic::peer_public_keys(interface) {
+            let server = match servers::SERVERS.iter().find(|s| s.public_key == peer) {
+                Some(s) => s,
+                None => continue,
+            };
+            let psk = open_for(sealed, server.key)?;
+            traffic::set_preshared_key(interface, &peer, psk.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+fn next_rotation_at(settings: &AppSettings) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let days = settings.psk_rotation_days?;
+    let rotated = chrono::DateTime::parse_from_rfc3339(settings.psk_rotated_at.as_deref()?).ok()?;
+    Some(rotated + chrono::Duration::days(days as i64))
+}
+
+pub fn is_due(settings: &AppSettings) -> bool {
+    next_rotation_at(settings).map_or(false, |at| chrono::Local::now() >= at)
+}
+
+/// Asks the API for new PSKs and switches to them. The server drops the old
+/// ones right away, so a live tunnel is updated in place immediately after.
+pub fn rotate(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<VpnState>();
+    let _guard = state
+        .rotation_lock
+        .try_lock()
+        .map_err(|_| "A key rotation is already in progress.".to_string())?;
+
+    let settings = load_settings();
+    provisioning::require_ready(&settings).map_err(|e| e.to_string())?;
+
+    let issued = state
+        .api
+        .rotate_preshared_keys(&settings.public_key)
+        .map_err(|e| format!("Preshared key rotation failed: {e}"))?;
+
+    update_settings(|settings| replace(settings, &issued))?;
+    let settings = load_settings();
+
+    // Kept still while the tunnel is updated
+    let _transition = state.transition_lock.lock().unwrap();
+    let interfaces = state
+        .active_scope
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|s| s.interfaces.clone())
+        .unwrap_or_default();
+    // Only the outer tunnel uses the device key; a multi-hop inner tunnel
+    // has its own
+    if *state.connected.lock().unwrap() && !interfaces.is_empty() {
+        apply_live(&interfaces[..1], &settings.preshared_keys)
+            .map_err(|e| format!("Preshared key rotation: updating tunnel failed: {e}"))?;
+    }
+
+    append_log(&format!(
+        "Preshared keys rotated ({} servers).",
+        settings.preshared_keys.len()
+    ))
+    .ok();
+    Ok(())
+}
+
+fn status(settings: &AppSettings) -> PskStatus {
+    PskStatus {
+        servers: settings.preshared_keys.keys().cloned().collect(),
+        rotated_at: settings.psk_rotated_at.clone(),
+        interval_days: settings.psk_rotation_days,
+        next_rotation_at: next_rotation_at(settings).map(|at| at.to_rfc3339()),
+    }
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_psk_status() -> PskStatus {
+    status(&load_settings())
+}
+
+/// Rotates PSKs every `days` days; `None` only rotates them with the key.
+#[tauri::command]
+pub fn set_psk_rotation_interval(days: Option<u32>) -> Result<PskStatus, NeraError> {
+    if let Some(d) = days {
+        if d == 0 || d > MAX_INTERVAL_DAYS {
+            return Err(NeraError::InvalidInput(format!(
+                "Rotation interval must be between 1 and {MAX_INTERVAL_DAYS} days."
+            )));
+        }
+    }
+
+    update_settings(|settings| settings.psk_rotation_days = days);
+
+    append_log(&format!(
+        "Preshared key rotation interval set to {days:?} days"
+    ))
+    .ok();
+    Ok(status(&load_settings()))
+}
+
+#[tauri::command]
+pub async fn rotate_psk_now(
+    app: AppHandle,
+    state: State<'_, VpnState>,
+) -> Result<PskStatus, NeraError> {
+    if !state.api.has_session() {
+        return Err(NeraError::ApiUnauthorized);
+    }
+    tauri::async_runtime::spawn_blocking(move || rotate(&app))
+        .await
+        .map_err(|e| format!("Preshared key rotation failed: {e}"))??;
+    Ok(get_psk_status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "[Interface]\nPrivateKey = a\nAddress = 10.0.0.2/32\n\n\
+                          [Peer]\nPublicKey = b\nPresharedKey = stale\nEndpoint = 1.2.3.4:51820\n";
+
+    fn psk(byte: u8) -> String {
+        general_purpose::STANDARD.encode([byte; 32])
+    }
+
+    fn peer_lines(config: &str) -> Vec<&str> {
+        config
+            .lines()
+            .skip_while(|l| l.trim() != "[Peer]")
+            .collect()
+    }
+
+    #[test]
+    fn psk_goes_under_the_peer_after_its_key() {
+        let sealed = seal_all(&BTreeMap::from([("tokyo".to_string(), psk(1))])).unwrap();
+        let config = apply_sealed(CONFIG, &sealed, "tokyo").unwrap();
+
+        let expected = format!("PresharedKey = {}", psk(1));
+        assert_eq!(
+            peer_lines(&config),
+            [
+                "[Peer]",
+                "PublicKey = b",
+                expected.as_str(),
+                "Endpoint = 1.2.3.4:51820"
+            ]
+        );
+        assert_eq!(config.matches("PresharedKey").count(), 1);
+    }
+
+    #[test]
+    fn no_psk_line_without_a_key_for_the_server() {
+        let sealed = seal_all(&BTreeMap::from([("osaka".to_string(), psk(1))])).unwrap();
+        for sealed in [BTreeMap::new(), sealed] {
+            let config = apply_sealed(CONFIG, &sealed, "tokyo").unwrap();
+            assert!(!config.contains("PresharedKey"), "{config}");
+            assert!(config.contains("PublicKey = b\nEndpoint"));
+        }
+    }
+
+    #[test]
+    fn replace_drops_stale_and_malformed_keys() {
+        let mut settings = AppSettings::default();
+        replace(
+            &mut settings,
+            &BTreeMap::from([("osaka".to_string(), psk(1))]),
+        )
+        .unwrap();
+        assert!(settings.psk_rotated_at.is_some());
+
+        let issued = BTreeMap::from([
+            ("tokyo".to_string(), psk(2)),
+            ("broken".to_string(), "short".to_string()),
+        ]);
+        replace(&mut settings, &issued).unwrap();
+        assert_eq!(
+            settings.preshared_keys.keys().collect::<Vec<_>>(),
+            ["tokyo"]
+        );
+        assert_eq!(
+            open_for(&settings.preshared_keys, "tokyo").unwrap(),
+            Some(psk(2))
+        );
+
+        replace(&mut settings, &BTreeMap::new()).unwrap();
+        assert!(settings.preshared_keys.is_empty());
+        assert_eq!(settings.psk_rotated_at, None);
+    }
+}
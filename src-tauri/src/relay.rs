@@ -0,0 +1,303 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Local UDP-over-TCP relay for networks that block WireGuard's UDP.
+//
+// WireGuard is pointed at a UDP socket on 127.0.0.1; every datagram it sends is
+// framed as `[u16 big-endian length][payload]` and written to a TCP connection to
+// the server-side relay, and frames coming back are unwrapped and sent to
+// WireGuard's socket. If the TCP connection drops we reconnect and keep going.
+// The relay serves only the first local sender (WireGuard); datagrams from any
+// other loopback port are dropped, so another process can't inject traffic or
+// take over the return path.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream, UdpSocket},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::append_log;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const MAX_DATAGRAM: usize = u16::MAX as usize;
+
+pub struct UdpTcpRelay {
+    local_addr: SocketAddr,
+    remote: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+}
+
+/// Writes one datagram as a length-prefixed frame.
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "datagram too large"))?;
+    let mut frame = Vec::with_capacity(payload.len() + 2);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+/// Reads one length-prefixed frame into `buf` and returns the payload length.
+pub fn read_frame<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = [0u8; 2];
+    reader.read_exact(&mut len)?;
+    let len = u16::from_be_bytes(len) as usize;
+    if len > buf.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    reader.read_exact(&mut buf[..len])?;
+    Ok(len)
+}
+
+/// Locks the relay to the first sender; `false` for datagrams from anyone else.
+fn accept_sender(peer: &Mutex<Option<SocketAddr>>, from: SocketAddr) -> bool {
+    let mut peer = peer.lock().unwrap();
+    match *peer {
+        Some(addr) => addr == from,
+        None => {
+            *peer = Some(from);
+            true
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl UdpTcpRelay {
+    /// Binds the local UDP side on loopback and starts relaying to `remote`.
+    /// Fails if the first TCP connection can't be made.
+    pub fn start(remote: SocketAddr) -> io::Result<Self> {
+        let udp = UdpSocket::bind("127.0.0.1:0")?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_addr = udp.local_addr()?;
+
+        let first = TcpStream::connect_timeout(&remote, CONNECT_TIMEOUT)?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let stop = shutdown.clone();
+
+        thread::spawn(move || {
+            // WireGuard's own source port, learned from its first datagram and
+            // kept across reconnects
+            let peer: Arc<Mutex<Option<SocketAddr>>> = Arc::new(Mutex::new(None));
+            let mut next = Some(first);
+
+            while !stop.load(Ordering::Relaxed) {
+                let tcp = match next.take() {
+                    Some(tcp) => tcp,
+                    None => match TcpStream::connect_timeout(&remote, CONNECT_TIMEOUT) {
+                        Ok(tcp) => {
+                            append_log("Relay: TCP connection re-established.").ok();
+                            tcp
+                        }
+                        Err(e) => {
+                            append_log(&format!("Relay: reconnect failed: {e}")).ok();
+                            thread::sleep(RECONNECT_DELAY);
+                            continue;
+                        }
+                    },
+                };
+
+                if let Err(e) = Self::pump(&udp, tcp, &peer, &stop) {
+                    append_log(&format!("Relay: connection lost: {e}")).ok();
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+
+        append_log(&format!(
+            "Relay: listening on {local_addr}, forwarding to {remote} over TCP"
+        ))
+        .ok();
+
+        Ok(UdpTcpRelay {
+            local_addr,
+            remote,
+            shutdown,
+        })
+    }
+
+    /// Moves traffic in both directions over one TCP connection until it fails
+    /// or the relay is stopped.
+    fn pump(
+        udp: &UdpSocket,
+        tcp: TcpStream,
+        peer: &Arc<Mutex<Option<SocketAddr>>>,
+        stop: &Arc<AtomicBool>,
+    ) -> io::Result<()> {
+        tcp.set_nodelay(true)?;
+        tcp.set_read_timeout(None)?;
+
+        // TCP -> UDP on its own thread
+        let mut tcp_reader = tcp.try_clone()?;
+        let udp_writer = udp.try_clone()?;
+        let reader_peer = peer.clone();
+        let reader_done = Arc::new(AtomicBool::new(false));
+        let reader_flag = reader_done.clone();
+        thread::spawn(move || {
+            let mut buf = vec![0u8; MAX_DATAGRAM];
+            while let Ok(len) = read_frame(&mut tcp_reader, &mut buf) {
+                if let Some(addr) = *reader_peer.lock().unwrap() {
+                    let _ = udp_writer.send_to(&buf[..len], addr);
+                }
+            }
+            reader_flag.store(true, Ordering::Relaxed);
+        });
+
+        // UDP -> TCP here
+        let mut tcp_writer = tcp;
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let result = loop {
+            if stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            if reader_done.load(Ordering::Relaxed) {
+                break Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "server closed the connection",
+                ));
+            }
+
+            match udp.recv_from(&mut buf) {
+                // Dropped without logging, so a flood can't fill the log
+                Ok((_, from)) if !accept_sender(peer, from) => continue,
+                Ok((len, _)) => {
+                    if let Err(e) = write_frame(&mut tcp_writer, &buf[..len]) {
+                        break Err(e);
+                    }
+                }
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => break Err(e),
+            }
+        };
+
+        let _ = tcp_writer.shutdown(Shutdown::Both);
+        result
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for UdpTcpRelay {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn frame_round_trip() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, b"hello").unwrap();
+        assert_eq!(&wire[..2], &[0, 5]);
+
+        let mut buf = [0u8; 16];
+        let len = read_frame(&mut io::Cursor::new(wire), &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }
+
+    #[test]
+    fn oversized_frame_is_rejected() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, &[0u8; 32]).unwrap();
+        let mut buf = [0u8; 16];
+        assert!(read_frame(&mut io::Cursor::new(wire), &mut buf).is_err());
+    }
+
+    #[test]
+    fn sender_is_locked_to_the_first() {
+        let peer = Mutex::new(None);
+        let first: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let other: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        assert!(accept_sender(&peer, first));
+        assert!(accept_sender(&peer, first));
+        assert!(!accept_sender(&peer, other));
+    }
+
+    /// Server-side relay stand-in that echoes every frame back.
+    fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                thread::spawn(move || {
+                    let mut buf = vec![0u8; MAX_DATAGRAM];
+                    while let Ok(len) = read_frame(&mut stream, &mut buf) {
+                        if write_frame(&mut stream, &buf[..len]).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn relays_end_to_end_for_the_first_sender_only() {
+        let relay = UdpTcpRelay::start(echo_server()).unwrap();
+
+        let wireguard = UdpSocket::bind("127.0.0.1:0").unwrap();
+        wireguard
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        wireguard.send_to(b"handshake", relay.local_addr()).unwrap();
+        let mut buf = [0u8; 64];
+        let (len, from) = wireguard.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"handshake");
+        assert_eq!(from, relay.local_addr());
+
+        // A second local socket gets nothing back, and doesn't steal the
+        // return path either
+        let intruder = UdpSocket::bind("127.0.0.1:0").unwrap();
+        intruder
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        intruder.send_to(b"inject", relay.local_addr()).unwrap();
+        assert!(intruder.recv_from(&mut buf).is_err());
+
+        wireguard.send_to(b"data", relay.local_addr()).unwrap();
+        let (len, _) = wireguard.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"data");
+
+        relay.stop();
+    }
+}
@@ -0,0 +1,194 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Encryption at rest for secrets kept in settings.json.
+//
+// On Windows values are sealed with DPAPI for the current user, so a copied
+// settings file is useless under another account or on another machine.
+// Elsewhere they're sealed with ChaCha20-Poly1305 under a random per-install
+// key kept in an owner-only file next to the settings.
+
+use base64::{engine::general_purpose, Engine as _};
+
+/// Encrypts `plain` into a base64 string that's safe to persist.
+pub fn seal(plain: &str) -> Result<String, String> {
+    let sealed = platform::protect(plain.as_bytes())?;
+    Ok(general_purpose::STANDARD.encode(sealed))
+}
+
+/// Reverses [`seal`].
+pub fn open(sealed: &str) -> Result<String, String> {
+    let bytes = general_purpose::STANDARD
+        .decode(sealed.trim())
+        .map_err(|e| format!("Stored secret is not valid base64: {e}"))?;
+    let plain = platform::unprotect(&bytes)?;
+    String::from_utf8(plain).map_err(|_| "Stored secret is not valid UTF-8.".to_string())
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{ptr, slice};
+
+    use windows_sys::Win32::{
+        Foundation::LocalFree,
+        Security::Cryptography::{
+            CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+        },
+    };
+
+    fn blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        }
+    }
+
+    /// Copies DPAPI's output and frees the buffer it allocated.
+    unsafe fn take(out: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        let data = slice::from_raw_parts(out.pbData, out.cbData as usize).to_vec();
+        LocalFree(out.pbData as _);
+        data
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+        let input = blob(data);
+        let mut out = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+        let ok = unsafe {
+            CryptProtectData(
+                &input,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut out,
+            )
+        };
+        if ok == 0 {
+            return Err(format!(
+                "Encrypting secret failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(unsafe { take(out) })
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+        let input = blob(data);
+        let mut out = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+        let ok = unsafe {
+            CryptUnprotectData(
+                &input,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut out,
+            )
+        };
+        if ok == 0 {
+            return Err(format!(
+                "Decrypting secret failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(unsafe { take(out) })
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    #[cfg(not(test))]
+    use std::{fs, io::Write, path::PathBuf};
+
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit},
+        ChaCha20Poly1305, Key, Nonce,
+    };
+    use rand::RngCore;
+
+    #[cfg(not(test))]
+    use crate::log_dir;
+
+    const NONCE_LEN: usize = 12;
+
+    #[cfg(not(test))]
+    fn key_path() -> Result<PathBuf, String> {
+        log_dir().map(|mut dir| {
+            dir.push("secrets.key");
+            dir
+        })
+    }
+
+    #[cfg(not(test))]
+    fn write_new_key(path: &PathBuf) -> Result<[u8; 32], String> {
+        let mut key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)
+            .and_then(|mut f| f.write_all(&key))
+            .map_err(|e| format!("Failed to create secrets key: {e}"))?;
+        Ok(key)
+    }
+
+    #[cfg(not(test))]
+    fn cipher() -> Result<ChaCha20Poly1305, String> {
+        let path = key_path()?;
+        let key = match fs::read(&path) {
+            Ok(bytes) => <[u8; 32]>::try_from(bytes.as_slice())
+                .map_err(|_| "Secrets key file is corrupt.".to_string())?,
+            Err(_) => write_new_key(&path)?,
+        };
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    // Tests never touch the user's key file
+    #[cfg(test)]
+    fn cipher() -> Result<ChaCha20Poly1305, String> {
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&[7; 32])))
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = cipher()?
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .map_err(|_| "Encrypting secret failed.".to_string())?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.len() < NONCE_LEN {
+            return Err("Stored secret is truncated.".to_string());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Decrypting secret failed.".to_string())
+    }
+}
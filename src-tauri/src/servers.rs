@@ -0,0 +1,342 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Server catalog and automatic fastest-server selection.
+//
+// `auto` probes every endpoint in the catalog concurrently, blends the result with
+// the last week of persisted probe history and picks the best-ranked reachable node.
+
+use std::{
+    fs,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{append_log, error::NeraError, latency, log_dir};
+
+pub const AUTO_SERVER_KEY: &str = "auto";
+
+const PROBES_PER_SERVER: usize = 3;
+const HISTORY_MAX_AGE_SECS: i64 = 7 * 24 * 60 * 60;
+// How much a single percent of packet loss costs, in milliseconds of RTT
+const LOSS_PENALTY_MS: f64 = 10.0;
+// Weight of the fresh measurement vs. the weekly history average
+const LIVE_WEIGHT: f64 = 0.6;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ServerInfo {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub host: &'static str,
+    // WireGuard ports in order of preference; the first is the default
+    pub ports: &'static [u16],
+    pub public_key: &'static str,
+    // TCP port of the server-side UDP-over-TCP relay
+    pub relay_port: Option<u16>,
+}
+
+pub const SERVERS: &[ServerInfo] = &[ServerInfo {
+    key: "tokyo",
+    label: "Tokyo, Japan",
+    host: "45.76.106.63",
+    ports: &[443, 51820, 53, 123],
+    public_key: "tN0y3O5a/J7IkVK3WV4IFi6COgCSb5mHVxeQXS9iN3Y=",
+    relay_port: Some(443),
+}];
+
+pub fn find_server(key: &str) -> Option<&'static ServerInfo> {
+    SERVERS.iter().find(|s| s.key == key)
+}
+
+impl ServerInfo {
+    pub fn primary_port(&self) -> u16 {
+        self.ports.first().copied().unwrap_or(443)
+    }
+
+    pub fn endpoint(&self) -> String {
+        self.endpoint_on(self.primary_port())
+    }
+
+    pub fn endpoint_on(&self, port: u16) -> String {
+        format!("{}:{}", self.host, port)
+    }
+
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.socket_addr_on(self.primary_port())
+    }
+
+    pub fn socket_addr_on(&self, port: u16) -> Option<SocketAddr> {
+        (self.host, port).to_socket_addrs().ok()?.next()
+    }
+
+    pub fn relay_addr(&self) -> Option<SocketAddr> {
+        (self.host, self.relay_port?).to_socket_addrs().ok()?.next()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ProbeRecord {
+    server_key: String,
+    timestamp: i64,
+    rtt_ms: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ServerRanking {
+    pub key: String,
+    pub label: String,
+    pub rtt_ms: Option<f64>,
+    pub loss_pct: f64,
+    pub history_rtt_ms: Option<f64>,
+    pub history_loss_pct: Option<f64>,
+    pub score: Option<f64>,
+    pub reachable: bool,
+}
+
+fn probe_history_path() -> Result<PathBuf, String> {
+    let mut path = log_dir()?;
+    path.push("probe_history.json");
+    Ok(path)
+}
+
+fn load_probe_history() -> Vec<ProbeRecord> {
+    probe_history_path()
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn save_probe_history(history: &[ProbeRecord]) {
+    if let Ok(path) = probe_history_path() {
+        if let Ok(content) = serde_json::to_string(history) {
+            let _ = fs::write(path, content);
+        }
+    }
+}
+
+/// Average RTT and loss for one server over the retained history.
+fn history_summary(history: &[ProbeRecord], key: &str) -> Option<(Option<f64>, f64)> {
+    let records: Vec<&ProbeRecord> = history.iter().filter(|r| r.server_key == key).collect();
+    if records.is_empty() {
+        return None;
+    }
+
+    let rtts: Vec<f64> = records.iter().filter_map(|r| r.rtt_ms).collect();
+    let loss_pct = (records.len() - rtts.len()) as f64 * 100.0 / records.len() as f64;
+    let avg = if rtts.is_empty() {
+        None
+    } else {
+        Some(rtts.iter().sum::<f64>() / rtts.len() as f64)
+    };
+    Some((avg, loss_pct))
+}
+
+fn score(rtt_ms: Option<f64>, loss_pct: f64) -> Option<f64> {
+    rtt_ms.map(|rtt| rtt + loss_pct * LOSS_PENALTY_MS)
+}
+
+/// Probes every server in the catalog concurrently and returns them best-first.
+/// Unreachable servers are kept at the end so the UI can still show them.
+pub fn rank_all_servers() -> Vec<ServerRanking> {
+    let handles: Vec<_> = SERVERS
+        .iter()
+        .map(|server| {
+            let server = *server;
+            thread::spawn(move || {
+                let addr = server.socket_addr();
+                let samples: Vec<Option<f64>> = (0..PROBES_PER_SERVER)
+                    .map(|_| {
+                        addr.and_then(|a| latency::probe_rtt(a, latency::PROBE_TIMEOUT))
+                            .map(|(rtt, _)| rtt.as_secs_f64() * 1000.0)
+                    })
+                    .collect();
+                (server, samples)
+            })
+        })
+        .collect();
+
+    let results: Vec<(ServerInfo, Vec<Option<f64>>)> =
+        handles.into_iter().filter_map(|h| h.join().ok()).collect();
+
+    // Drop anything older than a week, summarise what came before this
+    // round, then record it
+    let now = chrono::Utc::now().timestamp();
+    let mut history = load_probe_history();
+    history.retain(|r| now - r.timestamp <= HISTORY_MAX_AGE_SECS);
+    let past: Vec<Option<(Option<f64>, f64)>> = results
+        .iter()
+        .map(|(server, _)| history_summary(&history, server.key))
+        .collect();
+    for (server, samples) in &results {
+        for rtt in samples {
+            history.push(ProbeRecord {
+                server_key: server.key.to_string(),
+                timestamp: now,
+                rtt_ms: *rtt,
+            });
+        }
+    }
+    save_probe_history(&history);
+
+    let mut rankings: Vec<ServerRanking> = results
+        .iter()
+        .zip(past)
+        .map(|((server, samples), history)| {
+            let replies: Vec<f64> = samples.iter().flatten().copied().collect();
+            let loss_pct = (samples.len() - replies.len()) as f64 * 100.0 / samples.len() as f64;
+            let rtt_ms = if replies.is_empty() {
+                None
+            } else {
+                Some(replies.iter().sum::<f64>() / replies.len() as f64)
+            };
+
+            let live = score(rtt_ms, loss_pct);
+            let past = history.and_then(|(rtt, loss)| score(rtt, loss));
+
+            // Only a live reply makes a server eligible; history just nudges the order.
+            let blended = match (live, past) {
+                (Some(l), Some(p)) => Some(l * LIVE_WEIGHT + p * (1.0 - LIVE_WEIGHT)),
+                (Some(l), None) => Some(l),
+                (None, _) => None,
+            };
+
+            ServerRanking {
+                key: server.key.to_string(),
+                label: server.label.to_string(),
+                rtt_ms,
+                loss_pct,
+                history_rtt_ms: history.and_then(|(rtt, _)| rtt),
+                history_loss_pct: history.map(|(_, loss)| loss),
+                score: blended,
+                reachable: rtt_ms.is_some(),
+            }
+        })
+        .collect();
+
+    rankings.sort_by(|a, b| match (a.score, b.score) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    rankings
+}
+
+/// Best server by past probes alone.
+fn best_by_history(history: &[ProbeRecord]) -> Option<&'static str> {
+    SERVERS
+        .iter()
+        .filter_map(|s| {
+            let (rtt, loss) = history_summary(history, s.key)?;
+            score(rtt, loss).map(|sc| (s.key, sc))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(key, _)| key)
+}
+
+/// Where `auto` goes when probing gives no answer: the best server of past
+/// rounds, else `selected` if it's a real server, else the first one.
+fn fallback_server(history: &[ProbeRecord], selected: &str) -> String {
+    best_by_history(history)
+        .or_else(|| find_server(selected).map(|s| s.key))
+        .unwrap_or(SERVERS[0].key)
+        .to_string()
+}
+
+/// Resolves `auto` to the best-ranked reachable server key. With
+/// `probes_blocked` (the kill switch only lets WireGuard out) nothing would
+/// answer, so it goes straight to the fallback.
+pub fn pick_fastest_server(probes_blocked: bool, selected: &str) -> String {
+    let rankings = if probes_blocked {
+        Vec::new()
+    } else {
+        rank_all_servers()
+    };
+    let best = match rankings.iter().find(|r| r.reachable) {
+        Some(best) => best,
+        None => {
+            let key = fallback_server(&load_probe_history(), selected);
+            append_log(&format!(
+                "Auto server selection: no probe replies{}, using {key}",
+                if probes_blocked {
+                    " (blocked by the kill switch)"
+                } else {
+                    ""
+                }
+            ))
+            .ok();
+            return key;
+        }
+    };
+
+    append_log(&format!(
+        "Auto server selection: picked {} (score {:.1}, rtt {:.1} ms, loss {:.0}%)",
+        best.key,
+        best.score.unwrap_or_default(),
+        best.rtt_ms.unwrap_or_default(),
+        best.loss_pct
+    ))
+    .ok();
+
+    best.key.clone()
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_servers() -> Vec<ServerInfo> {
+    SERVERS.to_vec()
+}
+
+#[tauri::command]
+pub async fn rank_servers() -> Result<Vec<ServerRanking>, NeraError> {
+    tauri::async_runtime::spawn_blocking(rank_all_servers)
+        .await
+        .map_err(|e| NeraError::Internal(format!("Server ranking failed: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &str, rtt_ms: Option<f64>) -> ProbeRecord {
+        ProbeRecord {
+            server_key: key.to_string(),
+            timestamp: 0,
+            rtt_ms,
+        }
+    }
+
+    #[test]
+    fn history_summary_averages_replies_and_counts_loss() {
+        let history = vec![
+            record("tokyo", Some(10.0)),
+            record("tokyo", Some(30.0)),
+            record("tokyo", None),
+            record("tokyo", None),
+            record("other", Some(1.0)),
+        ];
+        assert_eq!(history_summary(&history, "tokyo"), Some((Some(20.0), 50.0)));
+        assert_eq!(history_summary(&history, "missing"), None);
+    }
+
+    #[test]
+    fn fallback_prefers_history_then_selection() {
+        let history = vec![record("tokyo", Some(40.0))];
+        assert_eq!(fallback_server(&history, "nowhere"), "tokyo");
+        assert_eq!(fallback_server(&[], "tokyo"), "tokyo");
+        assert_eq!(fallback_server(&[], AUTO_SERVER_KEY), SERVERS[0].key);
+    }
+}
@@ -0,0 +1,449 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Connection session journal.
+//
+// One record per connection attempt: when it started and ended, where it went,
+// how long the first handshake took, how much data moved and why it ended.
+// Kept in `session_history.json` next to the log and exportable as CSV.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{append_log, error::NeraError, log_dir, traffic, usage::UsageTotals, VpnState};
+
+const MAX_RECORDS: usize = 1000;
+const HANDSHAKE_WAIT: Duration = Duration::from_secs(30);
+const HANDSHAKE_POLL: Duration = Duration::from_millis(250);
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    User,
+    Tray,
+    Error,
+    Network,
+    Quit,
+    IdentitySwitch,
+}
+
+impl DisconnectReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectReason::User => "user",
+            DisconnectReason::Tray => "tray",
+            DisconnectReason::Error => "error",
+            DisconnectReason::Network => "network",
+            DisconnectReason::Quit => "quit",
+            DisconnectReason::IdentitySwitch => "identity_switch",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub id: String,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub server_key: String,
+    pub endpoint: Option<String>,
+    pub handshake_ms: Option<u64>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub disconnect_reason: Option<DisconnectReason>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SessionHistoryPage {
+    pub records: Vec<SessionRecord>,
+    pub page: usize,
+    pub page_size: usize,
+    pub total: usize,
+}
+
+struct JournalInner {
+    records: Vec<SessionRecord>,
+    active: Option<String>,
+    // When each open attempt began; handshake time is measured from here.
+    started: Vec<(String, Instant)>,
+}
+
+pub struct SessionJournal {
+    inner: Mutex<JournalInner>,
+}
+
+fn journal_path() -> Result<PathBuf, String> {
+    let mut path = log_dir()?;
+    path.push("session_history.json");
+    Ok(path)
+}
+
+fn save_records(records: &[SessionRecord]) {
+    if let Ok(path) = journal_path() {
+        if let Ok(content) = serde_json::to_string(records) {
+            let _ = fs::write(path, content);
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl SessionJournal {
+    pub fn load() -> Self {
+        let records: Vec<SessionRecord> = journal_path()
+            .ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+
+        SessionJournal {
+            inner: Mutex::new(JournalInner {
+                records,
+                active: None,
+                started: Vec::new(),
+            }),
+        }
+    }
+
+    /// Opens a record for a new connection attempt and returns its id.
+    pub fn begin(&self, server_key: &str, endpoint: Option<String>) -> String {
+        let mut inner = self.inner.lock().unwrap();
+        let id = format!("{:016x}", rand::random::<u64>());
+
+        inner.records.push(SessionRecord {
+            id: id.clone(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            ended_at: None,
+            server_key: server_key.to_string(),
+            endpoint,
+            handshake_ms: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            disconnect_reason: None,
+            error: None,
+        });
+        let overflow = inner.records.len().saturating_sub(MAX_RECORDS);
+        inner.records.drain(..overflow);
+
+        inner.active = Some(id.clone());
+        inner
+            .started
+            .retain(|(_, at)| at.elapsed() < HANDSHAKE_WAIT);
+        inner.started.push((id.clone(), Instant::now()));
+        save_records(&inner.records);
+        id
+    }
+
+    /// Fills in the resolved server (e.g. after `auto`) and its endpoint.
+    pub fn set_target(&self, id: &str, server_key: &str, endpoint: Option<String>) {
+        self.update(id, |r| {
+            r.server_key = server_key.to_string();
+            r.endpoint = endpoint;
+        });
+    }
+
+    fn update<F: FnOnce(&mut SessionRecord)>(&self, id: &str, f: F) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(record) = inner.records.iter_mut().find(|r| r.id == id) {
+            f(record);
+        }
+        save_records(&inner.records);
+    }
+
+    /// Closes a connection attempt that never came up.
+    pub fn fail(&self, id: &str, error: &str) {
+        self.update(id, |r| {
+            r.ended_at = Some(chrono::Local::now().to_rfc3339());
+            r.disconnect_reason = Some(DisconnectReason::Error);
+            r.error = Some(error.to_string());
+        });
+        let mut inner = self.inner.lock().unwrap();
+        if inner.active.as_deref() == Some(id) {
+            inner.active = None;
+        }
+    }
+
+    /// Closes the active session, if any.
+    pub fn end(
+        &self,
+        reason: DisconnectReason,
+        totals: Option<UsageTotals>,
+        error: Option<String>,
+    ) {
+        let id = match self.inner.lock().unwrap().active.take() {
+            Some(id) => id,
+            None => return,
+        };
+        self.update(&id, |r| {
+            r.ended_at = Some(chrono::Local::now().to_rfc3339());
+            r.disconnect_reason = Some(reason);
+            r.error = error;
+            if let Some(t) = totals {
+                r.rx_bytes = t.rx_bytes;
+                r.tx_bytes = t.tx_bytes;
+            }
+        });
+    }
+
+    /// Time since `begin` opened the attempt, if it is still tracked.
+    fn elapsed_since_begin(&self, id: &str) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .started
+            .iter()
+            .find(|(sid, _)| sid == id)
+            .map(|(_, at)| at.elapsed())
+    }
+
+    fn set_handshake(&self, id: &str, elapsed: Duration) {
+        self.update(id, |r| r.handshake_ms = Some(elapsed.as_millis() as u64));
+        self.inner
+            .lock()
+            .unwrap()
+            .started
+            .retain(|(sid, _)| sid != id);
+    }
+
+    fn page(&self, page: usize, page_size: usize) -> SessionHistoryPage {
+        let inner = self.inner.lock().unwrap();
+        let total = inner.records.len();
+        // Newest first
+        let records = inner
+            .records
+            .iter()
+            .rev()
+            .skip(page.saturating_mul(page_size))
+            .take(page_size)
+            .cloned()
+            .collect();
+
+        SessionHistoryPage {
+            records,
+            page,
+            page_size,
+            total,
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::from(
+            "id,started_at,ended_at,server_key,endpoint,handshake_ms,rx_bytes,tx_bytes,disconnect_reason,error\n",
+        );
+        for r in &inner.records {
+            let fields = [
+                r.id.clone(),
+                r.started_at.clone(),
+                r.ended_at.clone().unwrap_or_default(),
+                r.server_key.clone(),
+                r.endpoint.clone().unwrap_or_default(),
+                r.handshake_ms.map(|v| v.to_string()).unwrap_or_default(),
+                r.rx_bytes.to_string(),
+                r.tx_bytes.to_string(),
+                r.disconnect_reason
+                    .map(|d| d.as_str().to_string())
+                    .unwrap_or_default(),
+                r.error.clone().unwrap_or_default(),
+            ];
+            let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            out.push_str(&line.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Waits for the tunnel's first handshake and records how long it took.
+pub fn watch_first_handshake(
+    journal: Arc<SessionJournal>,
+    session_id: String,
+    interface: String,
+    flag: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        // Measured from the start of the connect attempt, not from here:
+        // by the time the watcher runs the handshake has usually happened.
+        let watch_started = Instant::now();
+        while flag.load(Ordering::Relaxed) && watch_started.elapsed() < HANDSHAKE_WAIT {
+            if traffic::latest_handshake(&interface).is_some() {
+                let elapsed = journal
+                    .elapsed_since_begin(&session_id)
+                    .unwrap_or_else(|| watch_started.elapsed());
+                journal.set_handshake(&session_id, elapsed);
+                append_log(&format!("First handshake after {} ms", elapsed.as_millis())).ok();
+                return;
+            }
+            thread::sleep(HANDSHAKE_POLL);
+        }
+        append_log("No handshake observed within 30s of connecting.").ok();
+    });
+}
+
+// --- Tauri Commands ---
+
+#[tauri::command]
+pub fn get_session_history(
+    state: State<'_, VpnState>,
+    page: Option<usize>,
+    page_size: Option<usize>,
+) -> SessionHistoryPage {
+    let page_size = page_size.filter(|s| *s > 0).unwrap_or(DEFAULT_PAGE_SIZE);
+    state.journal.page(page.unwrap_or(0), page_size)
+}
+
+/// Only absolute `.csv` paths inside an existing directory are accepted, so
+/// the export can't be pointed at arbitrary files.
+fn validate_export_path(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err("Export path must be absolute.".into());
+    }
+    let is_csv = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("csv"))
+        .unwrap_or(false);
+    if !is_csv {
+        return Err("Export path must end in .csv.".into());
+    }
+    match path.parent() {
+        Some(dir) if dir.is_dir() => {}
+        _ => return Err("Export directory does not exist.".into()),
+    }
+    if path.is_dir()
+        || fs::symlink_metadata(path)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false)
+    {
+        return Err("Export path must be a regular file.".into());
+    }
+    Ok(())
+}
+
+/// Writes the journal as CSV and returns the path written.
+#[tauri::command]
+pub fn export_session_history(
+    state: State<'_, VpnState>,
+    path: Option<String>,
+) -> Result<String, NeraError> {
+    let path = match path {
+        Some(p) => {
+            let p = PathBuf::from(p);
+            validate_export_path(&p)?;
+            p
+        }
+        None => {
+            let mut p = log_dir()?;
+            p.push("session_history.csv");
+            p
+        }
+    };
+
+    fs::write(&path, state.journal.to_csv())
+        .map_err(|e| format!("Failed to export session history: {e}"))?;
+
+    append_log(&format!("Session history exported to {}", path.display())).ok();
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal() -> SessionJournal {
+        SessionJournal {
+            inner: Mutex::new(JournalInner {
+                records: Vec::new(),
+                active: None,
+                started: Vec::new(),
+            }),
+        }
+    }
+
+    fn record(id: &str) -> SessionRecord {
+        SessionRecord {
+            id: id.to_string(),
+            started_at: String::new(),
+            ended_at: None,
+            server_key: "us".to_string(),
+            endpoint: None,
+            handshake_ms: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            disconnect_reason: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn page_does_not_overflow() {
+        let j = journal();
+        j.inner.lock().unwrap().records.push(record("a"));
+        let page = j.page(usize::MAX, usize::MAX);
+        assert!(page.records.is_empty());
+        assert_eq!(page.total, 1);
+    }
+
+    #[test]
+    fn page_is_newest_first() {
+        let j = journal();
+        for id in ["a", "b", "c"] {
+            j.inner.lock().unwrap().records.push(record(id));
+        }
+        let page = j.page(0, 2);
+        let ids: Vec<&str> = page.records.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["c", "b"]);
+    }
+
+    #[test]
+    fn handshake_measured_from_begin() {
+        let j = journal();
+        j.inner
+            .lock()
+            .unwrap()
+            .started
+            .push(("a".to_string(), Instant::now() - Duration::from_millis(500)));
+        assert!(j.elapsed_since_begin("a").unwrap() >= Duration::from_millis(500));
+        assert!(j.elapsed_since_begin("b").is_none());
+    }
+
+    #[test]
+    fn export_path_validation() {
+        let dir = std::env::temp_dir();
+        assert!(validate_export_path(&dir.join("history.csv")).is_ok());
+        assert!(validate_export_path(&dir.join("history.CSV")).is_ok());
+        assert!(validate_export_path(&dir.join("history.txt")).is_err());
+        assert!(validate_export_path(Path::new("history.csv")).is_err());
+        assert!(validate_export_path(&dir.join("missing-dir/history.csv")).is_err());
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
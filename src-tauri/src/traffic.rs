@@ -0,0 +1,346 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Traffic monitor for the active tunnel.
+//
+// Counters are read only from the interface the tunnel backend created, or from
+// WireGuard's own per-peer transfer counters. If neither is readable we report
+// "unavailable" instead of guessing at some other NIC.
+
+use std::{
+    fs,
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::json;
+use sysinfo::Networks;
+use tauri::AppHandle;
+
+use crate::{events, helper, hidden_command, latency::LatencyReport, usage::UsageTracker};
+
+const WG_EXE: &str = r"C:\Program Files\WireGuard\wg.exe";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CounterSource {
+    Interface,
+    WireGuardPeer,
+}
+
+#[derive(Clone, Serialize)]
+struct TrafficPayload {
+    download: Option<u64>,
+    upload: Option<u64>,
+    ping: String,
+    interface: String,
+    source: Option<CounterSource>,
+    available: bool,
+}
+
+/// Output of `wg show <interface> <field>`, through the helper if there is one.
+fn wg_show(interface: &str, field: &str) -> Option<String> {
+    let params = json!({ "interface": interface, "field": field });
+    match helper::call("wg_show", params) {
+        Some(reply) => reply.ok()?.as_str().map(str::to_string),
+        None => run_wg_show(interface, field),
+    }
+}
+
+/// Runs `wg show` in this process (needs administrator rights).
+pub fn run_wg_show(interface: &str, field: &str) -> Option<String> {
+    let output = hidden_command(WG_EXE)
+        .args(["show", interface, field])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Sums rx/tx over all peers from `wg show <iface> transfer`
+/// (one `<peer>\t<rx>\t<tx>` line per peer).
+fn wireguard_peer_counters(interface: &str) -> Option<(u64, u64)> {
+    sum_transfer(&wg_show(interface, "transfer")?)
+}
+
+fn sum_transfer(stdout: &str) -> Option<(u64, u64)> {
+    let mut totals: Option<(u64, u64)> = None;
+    for line in stdout.lines() {
+        let mut fields = line.split_whitespace().skip(1);
+        let rx = fields.next().and_then(|v| v.parse::<u64>().ok());
+        let tx = fields.next().and_then(|v| v.parse::<u64>().ok());
+        if let (Some(rx), Some(tx)) = (rx, tx) {
+            let (sum_rx, sum_tx) = totals.unwrap_or((0, 0));
+            totals = Some((sum_rx + rx, sum_tx + tx));
+        }
+    }
+    totals
+}
+
+/// Unix time of the most recent handshake with any peer, if one has happened.
+pub fn latest_handshake(interface: &str) -> Option<u64> {
+    newest_handshake(&wg_show(interface, "latest-handshakes")?)
+}
+
+/// From `wg show <iface> latest-handshakes`; 0 means never.
+fn newest_handshake(stdout: &str) -> Option<u64> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1)?.parse::<u64>().ok())
+        .filter(|ts| *ts > 0)
+        .max()
+}
+
+/// Polls until the tunnel completes a handshake or `timeout` passes.
+pub fn wait_for_handshake(interface: &str, timeout: Duration) -> bool {
+    let started = std::time::Instant::now();
+    while started.elapsed() < timeout {
+        if latest_handshake(interface).is_some() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    false
+}
+
+/// Polls until a handshake newer than `since` (unix seconds) completes.
+pub fn wait_for_handshake_since(interface: &str, since: u64, timeout: Duration) -> bool {
+    let started = std::time::Instant::now();
+    while started.elapsed() < timeout {
+        if latest_handshake(interface).map_or(false, |ts| ts >= since) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+    false
+}
+
+/// Runs `wg set <interface> <setting...> <file>` with `secret` in the file.
+/// `wg` only takes keys from a file, so it's written to `key_dir` (which only
+/// SYSTEM and administrators may read) and removed again. Needs administrator
+/// rights; see `helper`.
+pub fn run_wg_set_secret(
+    key_dir: &Path,
+    interface: &str,
+    setting: &[&str],
+    secret: &str,
+) -> Result<(), String> {
+    let key_path = key_dir.join(format!("{interface}.key"));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&key_path)
+        .and_then(|mut f| f.write_all(secret.as_bytes()))
+        .map_err(|e| format!("Failed to write key file: {e}"))?;
+
+    let output = hidden_command(WG_EXE)
+        .arg("set")
+        .arg(interface)
+        .args(setting)
+        .arg(&key_path)
+        .output();
+    let _ = fs::remove_file(&key_path);
+
+    let output = output.map_err(|e| format!("Failed to run wg: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "wg set {interface} {} failed: {}",
+            setting.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// [`run_wg_set_secret`] without the helper: the key file goes in a fresh
+/// restricted directory under the temp dir, removed again afterwards.
+fn run_wg_set_secret_local(interface: &str, setting: &[&str], secret: &str) -> Result<(), String> {
+    let dir = std::env::temp_dir().join(format!("nera-{:016x}", rand::random::<u64>()));
+    // Fails if it exists, so nobody can have prepared it for us
+    fs::create_dir(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let result = helper::restrict_dir(&dir)
+        .and_then(|_| run_wg_set_secret(&dir, interface, setting, secret));
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Swaps the private key of a running interface in place.
+pub fn set_private_key(interface: &str, private_key: &str) -> Result<(), String> {
+    let params = json!({ "interface": interface, "key": private_key });
+    match helper::call("set_private_key", params) {
+        Some(reply) => reply.map(|_| ()).map_err(|e| e.to_string()),
+        None => run_wg_set_secret_local(interface, &["private-key"], private_key),
+    }
+}
+
+/// Public keys of the peers configured on `interface`.
+pub fn peer_public_keys(interface: &str) -> Vec<String> {
+    wg_show(interface, "peers")
+        .map(|stdout| {
+            stdout
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Endpoints of the peers configured on `interface`.
+pub fn peer_endpoints(interface: &str) -> Vec<SocketAddr> {
+    wg_show(interface, "endpoints")
+        .map(|stdout| {
+            stdout
+                .lines()
+                .filter_map(|l| l.split_whitespace().nth(1)?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Sets `peer`'s preshared key on a running interface; `None` removes it
+/// (WireGuard treats an all-zero key as none).
+pub fn set_preshared_key(interface: &str, peer: &str, psk: Option<&str>) -> Result<(), String> {
+    const NO_PSK: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    let psk = psk.unwrap_or(NO_PSK);
+    let params = json!({ "interface": interface, "peer": peer, "psk": psk });
+    match helper::call("set_preshared_key", params) {
+        Some(reply) => reply.map(|_| ()).map_err(|e| e.to_string()),
+        None => run_wg_set_secret_local(interface, &["peer", peer, "preshared-key"], psk),
+    }
+}
+
+/// Cumulative (rx, tx) for exactly `interface`, or `None` if it can't be read.
+pub fn read_counters(networks: &mut Networks, interface: &str) -> Counters {
+    networks.refresh_list();
+    if let Some((_, data)) = networks.iter().find(|(name, _)| name.as_str() == interface) {
+        return Some((
+            data.total_received(),
+            data.total_transmitted(),
+            CounterSource::Interface,
+        ));
+    }
+
+    wireguard_peer_counters(interface).map(|(rx, tx)| (rx, tx, CounterSource::WireGuardPeer))
+}
+
+type Counters = Option<(u64, u64, CounterSource)>;
+
+/// Bytes moved between two readings. Speeds need two readings from the same
+/// source; a counter reset (or a source switch) restarts the baseline instead
+/// of spiking.
+fn speeds(last: Counters, current: Counters) -> (Option<u64>, Option<u64>) {
+    match (last, current) {
+        (Some((last_rx, last_tx, last_src)), Some((rx, tx, src)))
+            if last_src == src && rx >= last_rx && tx >= last_tx =>
+        {
+            (Some(rx - last_rx), Some(tx - last_tx))
+        }
+        _ => (None, None),
+    }
+}
+
+pub fn spawn_traffic_monitor(
+    app: AppHandle,
+    flag: Arc<AtomicBool>,
+    interface: String,
+    latency: Arc<Mutex<LatencyReport>>,
+    usage: Arc<UsageTracker>,
+) {
+    thread::spawn(move || {
+        let mut networks = Networks::new_with_refreshed_list();
+        let mut last: Counters = None;
+
+        loop {
+            if !flag.load(Ordering::Relaxed) {
+                break;
+            }
+            thread::sleep(Duration::from_secs(1));
+
+            let current = read_counters(&mut networks, &interface);
+            let (download, upload) = speeds(last, current);
+            last = current;
+
+            if let (Some(rx), Some(tx)) = (download, upload) {
+                usage.record(&app, rx, tx);
+            }
+
+            events::emit_transient(
+                &app,
+                "traffic-update",
+                TrafficPayload {
+                    download,
+                    upload,
+                    ping: latency.lock().unwrap().ping_label(),
+                    interface: interface.clone(),
+                    source: current.map(|(_, _, src)| src),
+                    available: current.is_some(),
+                },
+            )
+            .ok();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_is_summed_over_peers() {
+        let stdout = "peerA=\t100\t20\npeerB=\t5\t7\nbroken line\n";
+        assert_eq!(sum_transfer(stdout), Some((105, 27)));
+        assert_eq!(sum_transfer(""), None);
+    }
+
+    #[test]
+    fn newest_handshake_ignores_never() {
+        assert_eq!(
+            newest_handshake("peerA=\t0\npeerB=\t1700000000\npeerC=\t1600000000\n"),
+            Some(1700000000)
+        );
+        assert_eq!(newest_handshake("peerA=\t0\n"), None);
+    }
+
+    #[test]
+    fn speeds_need_a_baseline_from_the_same_source() {
+        use CounterSource::*;
+        let reading = |rx, tx, src| Some((rx, tx, src));
+
+        assert_eq!(speeds(None, reading(10, 10, Interface)), (None, None));
+        assert_eq!(
+            speeds(reading(10, 20, Interface), reading(15, 26, Interface)),
+            (Some(5), Some(6))
+        );
+        // Counter reset, e.g. the interface came back up
+        assert_eq!(
+            speeds(reading(500, 500, Interface), reading(10, 10, Interface)),
+            (None, None)
+        );
+        assert_eq!(
+            speeds(reading(10, 10, Interface), reading(20, 20, WireGuardPeer)),
+            (None, None)
+        );
+        assert_eq!(speeds(reading(10, 10, Interface), None), (None, None));
+    }
+}
@@ -0,0 +1,350 @@
+/*
+  Nera VPN™
+  Copyright © 2025 Vio Holdings LLC. All rights reserved.
+  Nera VPN™ is a trademark of Vio Holdings LLC.
+  This software is proprietary and confidential. Unauthorized copying,
+  distribution, modification, or use of this software, via any medium,
+  is strictly prohibited without written permission from the copyright holder.
+  The source code and binaries are protected by copyright law and international treaties.
+*/
+
+// Persistent data usage accounting.
+//
+// The traffic monitor feeds per-second deltas in here; we roll them up per session,
+// per day and per month and flush to `usage.json` periodically and at session end.
+// An optional monthly quota raises `usage-quota-alert` at 80% and 100%.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::{
+    append_log, error::NeraError, events, load_settings, log_dir, update_settings, VpnState,
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_SESSIONS: usize = 500;
+const QUOTA_THRESHOLDS: [u8; 2] = [80, 100];
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, rx: u64, tx: u64) {
+        self.rx_bytes = self.rx_bytes.saturating_add(rx);
+        self.tx_bytes = self.tx_bytes.saturating_add(tx);
+    }
+
+    fn total(&self) -> u64 {
+        self.rx_bytes.saturating_add(self.tx_bytes)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub id: String,
+    pub server_key: String,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct UsageStore {
+    #[serde(default)]
+    sessions: Vec<SessionUsage>,
+    #[serde(default)]
+    daily: BTreeMap<String, UsageTotals>,
+    #[serde(default)]
+    monthly: BTreeMap<String, UsageTotals>,
+    // Highest quota threshold already alerted for each month
+    #[serde(default)]
+    alerts_sent: BTreeMap<String, u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageRange {
+    Session,
+    Day,
+    Month,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UsageEntry {
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Clone, Serialize)]
+struct QuotaAlertPayload {
+    threshold_pct: u8,
+    month: String,
+    used_bytes: u64,
+    quota_bytes: u64,
+}
+
+struct TrackerInner {
+    store: UsageStore,
+    active_session: Option<String>,
+    quota_bytes: Option<u64>,
+    last_flush: Instant,
+}
+
+pub struct UsageTracker {
+    inner: Mutex<TrackerInner>,
+    // False in the `nera` CLI: the app owns usage.json and may be running
+    persist: bool,
+}
+
+fn usage_path() -> Result<PathBuf, String> {
+    let mut path = log_dir()?;
+    path.push("usage.json");
+    Ok(path)
+}
+
+fn load_store() -> UsageStore {
+    usage_path()
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn save_store(store: &UsageStore) {
+    if let Ok(path) = usage_path() {
+        if let Ok(content) = serde_json::to_string(store) {
+            let _ = fs::write(path, content);
+        }
+    }
+}
+
+fn quota_bytes_from_settings() -> Option<u64> {
+    load_settings()
+        .monthly_quota_mb
+        .map(|mb| mb.saturating_mul(1024 * 1024))
+}
+
+impl UsageTracker {
+    pub fn load() -> Self {
+        UsageTracker {
+            inner: Mutex::new(TrackerInner {
+                store: load_store(),
+                active_session: None,
+                quota_bytes: quota_bytes_from_settings(),
+                last_flush: Instant::now(),
+            }),
+            persist: true,
+        }
+    }
+
+    /// Reads usage.json but never writes it back.
+    pub fn load_read_only() -> Self {
+        UsageTracker {
+            persist: false,
+            ..UsageTracker::load()
+        }
+    }
+
+    fn save(&self, store: &UsageStore) {
+        if self.persist {
+            save_store(store);
+        }
+    }
+
+    pub fn start_session(&self, server_key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let id = format!("{:016x}", rand::random::<u64>());
+        inner.store.sessions.push(SessionUsage {
+            id: id.clone(),
+            server_key: server_key.to_string(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            ended_at: None,
+            totals: UsageTotals::default(),
+        });
+
+        let overflow = inner.store.sessions.len().saturating_sub(MAX_SESSIONS);
+        inner.store.sessions.drain(..overflow);
+
+        inner.active_session = Some(id);
+        self.save(&inner.store);
+        inner.last_flush = Instant::now();
+    }
+
+    /// Closes the active session and returns what it transferred.
+    pub fn end_session(&self) -> Option<UsageTotals> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut totals = None;
+        if let Some(id) = inner.active_session.take() {
+            if let Some(session) = inner.store.sessions.iter_mut().find(|s| s.id == id) {
+                session.ended_at = Some(chrono::Local::now().to_rfc3339());
+                totals = Some(session.totals);
+            }
+        }
+        self.save(&inner.store);
+        inner.last_flush = Instant::now();
+        totals
+    }
+
+    /// Adds one interval's worth of traffic and raises quota alerts as needed.
+    pub fn record(&self, app: &AppHandle, rx: u64, tx: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = chrono::Local::now();
+        let day = now.format("%Y-%m-%d").to_string();
+        let month = now.format("%Y-%m").to_string();
+
+        if let Some(id) = inner.active_session.clone() {
+            if let Some(session) = inner.store.sessions.iter_mut().find(|s| s.id == id) {
+                session.totals.add(rx, tx);
+            }
+        }
+        inner.store.daily.entry(day).or_default().add(rx, tx);
+        let used = {
+            let totals = inner.store.monthly.entry(month.clone()).or_default();
+            totals.add(rx, tx);
+            totals.total()
+        };
+
+        let mut force_flush = false;
+        if let Some(quota) = inner.quota_bytes.filter(|q| *q > 0) {
+            let pct = used.saturating_mul(100) / quota;
+            let already = inner.store.alerts_sent.get(&month).copied().unwrap_or(0);
+            let crossed = QUOTA_THRESHOLDS
+                .iter()
+                .copied()
+                .filter(|t| pct >= *t as u64 && *t > already)
+                .max();
+
+            if let Some(threshold) = crossed {
+                inner.store.alerts_sent.insert(month.clone(), threshold);
+                append_log(&format!(
+                    "Usage quota: {threshold}% of monthly quota reached ({used} of {quota} bytes)"
+                ))
+                .ok();
+                events::emit(
+                    app,
+                    "usage-quota-alert",
+                    QuotaAlertPayload {
+                        threshold_pct: threshold,
+                        month,
+                        used_bytes: used,
+                        quota_bytes: quota,
+                    },
+                )
+                .ok();
+                force_flush = true;
+            }
+        }
+
+        if force_flush || inner.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.save(&inner.store);
+            inner.last_flush = Instant::now();
+        }
+    }
+
+    fn set_quota(&self, quota_bytes: Option<u64>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.quota_bytes == quota_bytes {
+            return;
+        }
+        inner.quota_bytes = quota_bytes;
+        // A different quota gets fresh alerts for the current month
+        let month = chrono::Local::now().format("%Y-%m").to_string();
+        inner.store.alerts_sent.remove(&month);
+        self.save(&inner.store);
+    }
+
+    fn query(&self, range: UsageRange, from: Option<&str>, to: Option<&str>) -> Vec<UsageEntry> {
+        let inner = self.inner.lock().unwrap();
+        // Periods are ISO-formatted, so plain string comparison orders them correctly
+        let in_range = |period: &str| {
+            from.map(|f| period >= f).unwrap_or(true) && to.map(|t| period <= t).unwrap_or(true)
+        };
+
+        match range {
+            UsageRange::Session => inner
+                .store
+                .sessions
+                .iter()
+                .filter(|s| in_range(&s.started_at[..10]))
+                .map(|s| UsageEntry {
+                    period: s.id.clone(),
+                    totals: s.totals,
+                })
+                .collect(),
+            UsageRange::Day => inner
+                .store
+                .daily
+                .iter()
+                .filter(|(day, _)| in_range(day))
+                .map(|(day, totals)| UsageEntry {
+                    period: day.clone(),
+                    totals: *totals,
+                })
+                .collect(),
+            UsageRange::Month => inner
+                .store
+                .monthly
+                .iter()
+                .filter(|(month, _)| in_range(month))
+                .map(|(month, totals)| UsageEntry {
+                    period: month.clone(),
+                    totals: *totals,
+                })
+                .collect(),
+        }
+    }
+
+    fn sessions(&self) -> Vec<SessionUsage> {
+        self.inner.lock().unwrap().store.sessions.clone()
+    }
+}
+
+// --- Tauri Commands ---
+
+/// `from`/`to` are inclusive and use the period's own format
+/// (`YYYY-MM-DD` for days and sessions, `YYYY-MM` for months).
+#[tauri::command]
+pub fn get_usage(
+    state: State<'_, VpnState>,
+    range: UsageRange,
+    from: Option<String>,
+    to: Option<String>,
+) -> Vec<UsageEntry> {
+    state.usage.query(range, from.as_deref(), to.as_deref())
+}
+
+#[tauri::command]
+pub fn get_usage_sessions(state: State<'_, VpnState>) -> Vec<SessionUsage> {
+    state.usage.sessions()
+}
+
+#[tauri::command]
+pub fn get_usage_quota() -> Option<u64> {
+    load_settings().monthly_quota_mb
+}
+
+#[tauri::command]
+pub fn set_usage_quota(state: State<'_, VpnState>, quota_mb: Option<u64>) -> Result<(), NeraError> {
+    let quota_mb = quota_mb.filter(|mb| *mb > 0);
+    update_settings(|settings| settings.monthly_quota_mb = quota_mb);
+
+    state
+        .usage
+        .set_quota(quota_mb.map(|mb| mb.saturating_mul(1024 * 1024)));
+
+    append_log(&format!("Monthly usage quota set to {quota_mb:?} MB")).ok();
+    Ok(())
+}